@@ -0,0 +1,73 @@
+// Builds the classic tablebase embedded into the game, or checks an existing one
+//
+//   cargo run --release --bin tablebase -- build [assets/tablebase.bin]
+//   cargo run --release --bin tablebase -- verify [assets/tablebase.bin]
+
+use std::{env, fs, process};
+
+use tictactoe::tablebase::*;
+
+const DEFAULT_PATH: &str = "assets/tablebase.bin";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = args.get(1).map_or(DEFAULT_PATH, |path| path.as_str());
+    let result = match args.first().map(|command| command.as_str()) {
+        Some("build") => build(path),
+        Some("verify") => verify(path),
+        _ => Err("usage: tablebase <build|verify> [path]".to_string()),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn build(path: &str) -> Result<(), String> {
+    let tablebase = Tablebase::build();
+    fs::write(path, tablebase.to_bytes()).map_err(|err| format!("failed to write {}: {}", path, err))?;
+    println!("wrote {} positions to {}", tablebase.entries().len(), path);
+    Ok(())
+}
+
+// Every reachable position, not just the canonical ones, has to agree with plain minimax
+fn verify(path: &str) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+    let tablebase = Tablebase::from_bytes(&bytes)?;
+    if tablebase.entries() != Tablebase::build().entries() {
+        return Err(format!("{} is out of date, rebuild it", path));
+    }
+
+    let mut checked = 0;
+    for code in 0..3u32.pow(9) {
+        let mut cells = [EMPTY; 9];
+        let mut rest = code;
+        for cell in cells.iter_mut() {
+            *cell = (rest % 3) as u8;
+            rest /= 3;
+        }
+
+        for to_move in [X, O] {
+            for misere in [false, true] {
+                let entry = tablebase.get(&cells, to_move, misere);
+                if !is_reachable(&cells, to_move) {
+                    if entry.is_some() {
+                        return Err(format!("unreachable position {:?} has an entry", cells));
+                    }
+                    continue;
+                }
+
+                let expected = brute_force(&cells, to_move, misere);
+                if entry != Some(expected) {
+                    return Err(format!(
+                        "{:?} with {} to move (misère {}): table has {:?}, minimax gives {:?}",
+                        cells, to_move, misere, entry, expected,
+                    ));
+                }
+                checked += 1;
+            }
+        }
+    }
+    println!("{} positions agree with minimax", checked);
+    Ok(())
+}
@@ -0,0 +1,399 @@
+// Plays games between computer players without opening a window and reports how they did
+//
+//   cargo run --release --bin tournament -- [options] <player> <player> [<player> ...]
+//
+// Players are `ai` (the in-game computer at Hard), `ai:easy`, `ai:medium`, `mcts:<iterations>`, `random` or
+// `engine:<command>` for an external engine, see logic/engine.rs.
+//
+//   --variant <name>       classic, ultimate, disappearing, gravity, cube3, cube4 or infinite
+//   --misere, --wild       rule options, like the M and W keys
+//   --games <n>            games per pairing, colors alternate (default 10)
+//   --gauntlet             the first player meets every other one, instead of everyone meeting
+//   --engine-time <ms>     time external engines get per move (default 1000)
+//   --csv <dir>            writes standings.csv, pairings.csv and openings.csv
+//   --json <file>          writes everything as one JSON document
+
+use std::{collections::BTreeMap, env, fs, path::Path, process, thread, time::{Duration, Instant}};
+
+use bevy::tasks::{AsyncComputeTaskPool, TaskPool};
+use rand::seq::SliceRandom;
+use serde::Serialize;
+
+use tictactoe::{data::*, logic::*};
+
+// Players that keep failing to answer lose the game instead of stalling the tournament
+const MOVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+enum Entrant {
+    Ai(Difficulty),
+    Mcts(u32),
+    Random,
+    Engine(String),
+}
+
+impl Entrant {
+    fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(':') {
+            _ if spec == "ai" => Ok(Entrant::Ai(Difficulty::Hard)),
+            Some(("ai", level)) => Difficulty::ALL.into_iter()
+                .find(|difficulty| difficulty.label().eq_ignore_ascii_case(level))
+                .map(Entrant::Ai)
+                .ok_or_else(|| format!("unknown difficulty in `{}`", spec)),
+            _ if spec == "random" => Ok(Entrant::Random),
+            Some(("mcts", iterations)) => iterations.parse()
+                .map(Entrant::Mcts)
+                .map_err(|_| format!("bad iteration count in `{}`", spec)),
+            Some(("engine", command)) => Ok(Entrant::Engine(command.to_string())),
+            _ => Err(format!("unknown player `{}`", spec)),
+        }
+    }
+
+    fn controller(&self, engine_time: Duration) -> Result<Box<dyn Controller>, String> {
+        Ok(match self {
+            Entrant::Ai(difficulty) => Box::new(AiController::new(*difficulty, &MctsSettings::default(), Duration::ZERO)),
+            // Iterations alone decide how long it thinks, so results don't depend on the machine
+            Entrant::Mcts(iterations) => Box::new(AiController::new(Difficulty::Hard, &MctsSettings {
+                iterations: *iterations,
+                time_budget: Duration::MAX,
+                ..MctsSettings::default()
+            }, Duration::ZERO)),
+            Entrant::Random => Box::new(RandomController),
+            Entrant::Engine(command) => Box::new(EngineController::spawn(command, engine_time)
+                .map_err(|err| format!("failed to start engine `{}`: {}", command, err))?),
+        })
+    }
+}
+
+struct RandomController;
+
+impl Controller for RandomController {
+    fn poll_move(&mut self, request: &MoveRequest) -> Option<Move> {
+        let mut rng = rand::thread_rng();
+        let pos = request.position.legal.choose(&mut rng)?;
+        let mark = if request.rules.wild {
+            *[CellState::X, CellState::O].choose(&mut rng).unwrap()
+        } else {
+            request.player
+        };
+        Some((*pos, mark))
+    }
+}
+
+struct Options {
+    rules: Rules,
+    games: usize,
+    gauntlet: bool,
+    engine_time: Duration,
+    csv: Option<String>,
+    json: Option<String>,
+    players: Vec<String>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        rules: Rules::default(),
+        games: 10,
+        gauntlet: false,
+        engine_time: Duration::from_secs(1),
+        csv: None,
+        json: None,
+        players: Vec::new(),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--variant" => {
+                let name = value()?;
+                options.rules.variant = *Variant::ALL.iter()
+                    .find(|variant| format!("{:?}", variant).eq_ignore_ascii_case(&name))
+                    .ok_or(format!("unknown variant `{}`", name))?;
+            },
+            "--misere" => options.rules.misere = true,
+            "--wild" => options.rules.wild = true,
+            "--games" => options.games = value()?.parse().map_err(|_| "bad game count".to_string())?,
+            "--gauntlet" => options.gauntlet = true,
+            "--engine-time" => {
+                let millis = value()?.parse().map_err(|_| "bad engine time".to_string())?;
+                options.engine_time = Duration::from_millis(millis);
+            },
+            "--csv" => options.csv = Some(value()?),
+            "--json" => options.json = Some(value()?),
+            _ => options.players.push(arg),
+        }
+    }
+    if options.players.len() < 2 {
+        return Err("a tournament needs at least two players".to_string());
+    }
+    Ok(options)
+}
+
+// Plays one game, a player that fails to answer or makes an illegal move loses it
+fn play_game(rules: Rules, players: &mut Players) -> Game {
+    let mut game = Game::new(rules, CellState::X);
+    players.x.new_game();
+    players.o.new_game();
+
+    while game.result.is_none() {
+        let player = game.to_move;
+        let controller = players.get_mut(player);
+        controller.start_turn();
+
+        let position = game.position();
+        let started = Instant::now();
+        let mut last_poll = started;
+        let mv = loop {
+            let request = MoveRequest {
+                player,
+                rules,
+                position: &position,
+                local_move: None,
+                delta: last_poll.elapsed(),
+            };
+            last_poll = Instant::now();
+            if let Some(mv) = controller.poll_move(&request) { break Some(mv); }
+            if started.elapsed() > MOVE_TIMEOUT { break None; }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        let played = mv.ok_or("no move".to_string()).and_then(|mv| game.play(mv));
+        if let Err(err) = played {
+            eprintln!("{:?} forfeits: {}", player, err);
+            game.result = Some(player.opponent());
+        }
+    }
+    game
+}
+
+#[derive(Serialize, Default, Clone)]
+struct Record {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Record {
+    fn add(&mut self, score: f32) {
+        match score {
+            s if s > 0.5 => self.wins += 1,
+            s if s < 0.5 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+}
+
+#[derive(Serialize)]
+struct Standing {
+    player: String,
+    #[serde(flatten)]
+    record: Record,
+    elo: f64,
+}
+
+#[derive(Serialize)]
+struct Pairing {
+    player: String,
+    opponent: String,
+    #[serde(flatten)]
+    record: Record,
+}
+
+#[derive(Serialize)]
+struct Opening {
+    // First move in canonical form, the same for all its rotations and reflections
+    opening: String,
+    games: u32,
+    first_player_wins: u32,
+    draws: u32,
+    second_player_wins: u32,
+}
+
+#[derive(Serialize)]
+struct Report {
+    standings: Vec<Standing>,
+    pairings: Vec<Pairing>,
+    openings: Vec<Opening>,
+}
+
+// Ratings that best explain the results, found by fitting the Elo expected score to them.
+// They average 1500, only the differences mean anything.
+fn estimate_elo(players: usize, results: &[(usize, usize, f32)]) -> Vec<f64> {
+    let mut ratings = vec![1500.; players];
+    for _ in 0..200 {
+        let mut gradient = vec![0.; players];
+        let mut games = vec![0.; players];
+        for (a, b, score) in results.iter() {
+            let expected = 1. / (1. + 10f64.powf((ratings[*b] - ratings[*a]) / 400.));
+            gradient[*a] += *score as f64 - expected;
+            gradient[*b] -= *score as f64 - expected;
+            games[*a] += 1.;
+            games[*b] += 1.;
+        }
+        for player in 0..players {
+            if games[player] > 0. {
+                ratings[player] += 100. * gradient[player] / games[player];
+            }
+        }
+        let mean = ratings.iter().sum::<f64>() / players as f64;
+        ratings.iter_mut().for_each(|rating| *rating += 1500. - mean);
+    }
+    ratings
+}
+
+// Square boards can be turned, so openings that are rotations or reflections of each other
+// count as one. Gravity boards are only the same mirrored, and their first move is a column.
+fn opening_name(rules: &Rules, (pos, _): Move) -> String {
+    if rules.variant == Variant::Gravity {
+        return format!("col {}", pos.col.abs());
+    }
+    let (form, _) = canonical_marks(rules.variant, [(pos, CellState::X)]);
+    let (row, col, layer, _) = form[0];
+    format!("{},{},{}", row, col, layer)
+}
+
+fn write_csv(dir: &str, report: &Report) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let dir = Path::new(dir);
+
+    let mut standings = "player,wins,draws,losses,elo\n".to_string();
+    for standing in report.standings.iter() {
+        let record = &standing.record;
+        standings += &format!("{},{},{},{},{:.0}\n", standing.player, record.wins, record.draws, record.losses, standing.elo);
+    }
+    fs::write(dir.join("standings.csv"), standings)?;
+
+    let mut pairings = "player,opponent,wins,draws,losses\n".to_string();
+    for pairing in report.pairings.iter() {
+        let record = &pairing.record;
+        pairings += &format!("{},{},{},{},{}\n", pairing.player, pairing.opponent, record.wins, record.draws, record.losses);
+    }
+    fs::write(dir.join("pairings.csv"), pairings)?;
+
+    let mut openings = "opening,games,first_player_wins,draws,second_player_wins\n".to_string();
+    for opening in report.openings.iter() {
+        openings += &format!(
+            "\"{}\",{},{},{},{}\n",
+            opening.opening, opening.games, opening.first_player_wins, opening.draws, opening.second_player_wins,
+        );
+    }
+    fs::write(dir.join("openings.csv"), openings)
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_options()?;
+    let entrants = options.players.iter()
+        .map(|spec| Entrant::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    // The computer searches big boards on the async compute pool, like in game
+    AsyncComputeTaskPool::init(TaskPool::new);
+
+    let pairs: Vec<(usize, usize)> = if options.gauntlet {
+        (1..entrants.len()).map(|other| (0, other)).collect()
+    } else {
+        (0..entrants.len()).flat_map(|a| (a + 1..entrants.len()).map(move |b| (a, b))).collect()
+    };
+
+    // (player, opponent, score of player) for every game
+    let mut results = Vec::new();
+    let mut openings: BTreeMap<String, Opening> = BTreeMap::new();
+    for (a, b) in pairs {
+        for game_index in 0..options.games {
+            // Colors alternate so neither player always gets to start
+            let (x, o) = if game_index % 2 == 0 { (a, b) } else { (b, a) };
+            let mut players = Players {
+                x: entrants[x].controller(options.engine_time)?,
+                o: entrants[o].controller(options.engine_time)?,
+            };
+            let game = play_game(options.rules, &mut players);
+
+            let x_score = match game.result {
+                Some(CellState::X) => 1.,
+                Some(CellState::O) => 0.,
+                _ => 0.5,
+            };
+            results.push((x, o, x_score));
+            println!(
+                "{} vs {}: {} in {} moves",
+                options.players[x], options.players[o],
+                match game.result { Some(CellState::X) => "1-0", Some(CellState::O) => "0-1", _ => "½-½" },
+                game.moves.len(),
+            );
+
+            if let Some(first_move) = game.moves.first() {
+                let name = opening_name(&options.rules, *first_move);
+                let opening = openings.entry(name.clone()).or_insert(Opening {
+                    opening: name,
+                    games: 0,
+                    first_player_wins: 0,
+                    draws: 0,
+                    second_player_wins: 0,
+                });
+                opening.games += 1;
+                match game.result {
+                    Some(CellState::X) => opening.first_player_wins += 1,
+                    Some(CellState::O) => opening.second_player_wins += 1,
+                    _ => opening.draws += 1,
+                }
+            }
+        }
+    }
+
+    let elo = estimate_elo(entrants.len(), &results);
+    let mut records = vec![Record::default(); entrants.len()];
+    let mut pairing_records: BTreeMap<(usize, usize), Record> = BTreeMap::new();
+    for (a, b, score) in results.iter() {
+        records[*a].add(*score);
+        records[*b].add(1. - *score);
+        pairing_records.entry((*a, *b)).or_default().add(*score);
+        pairing_records.entry((*b, *a)).or_default().add(1. - *score);
+    }
+
+    let mut standings: Vec<_> = options.players.iter().zip(records).zip(elo)
+        .map(|((player, record), elo)| Standing { player: player.clone(), record, elo })
+        .collect();
+    standings.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+    let report = Report {
+        standings,
+        pairings: pairing_records.into_iter()
+            .map(|((a, b), record)| Pairing {
+                player: options.players[a].clone(),
+                opponent: options.players[b].clone(),
+                record,
+            })
+            .collect(),
+        openings: openings.into_values().collect(),
+    };
+
+    println!();
+    println!("{:<24} {:>6} {:>6} {:>6} {:>6}", "player", "won", "drawn", "lost", "elo");
+    for standing in report.standings.iter() {
+        let record = &standing.record;
+        println!(
+            "{:<24} {:>6} {:>6} {:>6} {:>6.0}",
+            standing.player, record.wins, record.draws, record.losses, standing.elo,
+        );
+    }
+    let total: u32 = report.standings.iter().map(|standing| standing.record.games()).sum::<u32>() / 2;
+    println!("{} games", total);
+
+    if let Some(dir) = &options.csv {
+        write_csv(dir, &report).map_err(|err| format!("failed to write {}: {}", dir, err))?;
+    }
+    if let Some(path) = &options.json {
+        let json = serde_json::to_string_pretty(&report).map_err(|err| err.to_string())?;
+        fs::write(path, json).map_err(|err| format!("failed to write {}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+// Games a profile has to draw in a row for Achievement::DrawStreak
+pub const DRAW_STREAK: usize = 10;
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Achievement {
+    DiagonalWin,
+    // Profiles saved before the computer had difficulties call it BeatComputer
+    #[serde(alias = "BeatComputer")]
+    BeatHardAi,
+    DrawStreak,
+    // Won with no more than three marks of one's own
+    QuickWin,
+}
+
+impl Achievement {
+    pub const ALL: [Achievement; 4] = [
+        Achievement::DiagonalWin,
+        Achievement::BeatHardAi,
+        Achievement::DrawStreak,
+        Achievement::QuickWin,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Achievement::DiagonalWin => "Win with a diagonal",
+            Achievement::BeatHardAi => "Beat the Hard AI",
+            Achievement::DrawStreak => "Draw 10 games in a row",
+            Achievement::QuickWin => "Win in 3 moves",
+        }
+    }
+
+    // Theme that can only be picked once some local profile has the achievement
+    pub fn theme_reward(&self) -> Option<&'static str> {
+        match self {
+            Achievement::BeatHardAi => Some("themes/sunset.theme.ron"),
+            Achievement::DrawStreak => Some("themes/midnight.theme.ron"),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use serde::Deserialize;
+
+#[derive(TypeUuid)]
+#[uuid = "0d6f4b8e-2a4c-4f7e-9b61-7c0e5a1d3f92"]
+pub struct AtlasManifest {
+    pub image: Handle<Image>,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+#[derive(Deserialize)]
+pub struct AtlasManifestFile {
+    pub image: String,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AtlasRegion {
+    pub min: (u32, u32),
+    pub max: (u32, u32),
+    // Regions reusing part of a bigger sprite must say so, otherwise overlaps are an error
+    #[serde(default)]
+    pub part_of: Option<String>,
+}
+
+impl AtlasRegion {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            min: Vec2::new(self.min.0 as f32, self.min.1 as f32),
+            max: Vec2::new(self.max.0 as f32, self.max.1 as f32),
+        }
+    }
+
+    pub fn overlaps(&self, other: &AtlasRegion) -> bool {
+        self.min.0 < other.max.0 && other.min.0 < self.max.0
+            && self.min.1 < other.max.1 && other.min.1 < self.max.1
+    }
+
+    pub fn contains(&self, other: &AtlasRegion) -> bool {
+        self.min.0 <= other.min.0 && other.max.0 <= self.max.0
+            && self.min.1 <= other.min.1 && other.max.1 <= self.max.1
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    MissingRegion(String),
+    EmptyRegion(String),
+    UnknownParent { region: String, parent: String },
+    NotInParent { region: String, parent: String },
+    Overlapping(String, String),
+    OutOfBounds { region: String, image_size: (u32, u32) },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::MissingRegion(name) => write!(f, "missing region {:?}", name),
+            AtlasError::EmptyRegion(name) => write!(f, "region {:?} has no area", name),
+            AtlasError::UnknownParent { region, parent } =>
+                write!(f, "region {:?} is part of unknown region {:?}", region, parent),
+            AtlasError::NotInParent { region, parent } =>
+                write!(f, "region {:?} is not inside region {:?}", region, parent),
+            AtlasError::Overlapping(a, b) => write!(f, "regions {:?} and {:?} overlap", a, b),
+            AtlasError::OutOfBounds { region, image_size } => write!(
+                f, "region {:?} lies outside the {}x{} image", region, image_size.0, image_size.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
@@ -1,16 +1,65 @@
 use super::*;
 
 pub struct GameOverEvent {
-    pub last_picked_cell_ent: Entity,
-    pub last_picked_cell_state: CellState,
-    pub winning_positions: [CellPosition; 3]
+    // CellState::None for a draw
+    pub winner: CellState,
+    // None for draws and games lost on time
+    pub winning_positions: Option<Vec<CellPosition>>,
+}
+
+pub struct SubBoardWonEvent {
+    pub sub_board: CellPosition,
+    pub winner: CellState,
 }
 
 pub struct CellPickedEvent {
     pub entity: Entity,
+    // Mark placed on the cell
     pub state: CellState,
+    // Side that made the move, only differs from the mark in wild tic-tac-toe
+    pub player: CellState,
     pub position: CellPosition,
 }
 
+// Cell clicked by the local player, played if their side is the one to move
+pub struct LocalMoveEvent {
+    pub entity: Entity,
+    pub state: CellState,
+}
+
 pub struct PlayBtnClickedEvt;
-pub struct QuitBtnClickedEvt;
\ No newline at end of file
+pub struct NewGameEvent;
+// Sent by reset_game once the cells of the new game are empty
+pub struct BoardClearedEvent;
+pub struct QuitBtnClickedEvt;
+pub struct NextThemeEvent;
+pub struct NextClockModeEvent;
+pub struct NextVariantEvent;
+pub struct ToggleMisereEvent;
+pub struct ToggleWildEvent;
+pub struct NextFirstPlayerEvent;
+pub struct TogglePieRuleEvent;
+pub struct SwapSidesEvent;
+pub struct HintRequestedEvent;
+pub struct ToggleEvalOverlayEvent;
+pub struct ToggleProfileStatsEvent;
+pub struct ToggleTutorialEvent;
+
+pub struct AchievementUnlockedEvent {
+    pub profile: String,
+    pub achievement: Achievement,
+}
+
+// Profile changes for the profile playing a side
+pub struct NextProfileEvent(pub CellState);
+pub struct NextMarkColorEvent(pub CellState);
+pub struct NextAvatarEvent(pub CellState);
+
+pub struct HintGivenEvent {
+    pub entity: Entity,
+    pub state: CellState,
+    pub reason: HintReason,
+}
+pub struct SwapMarkChoiceEvent;
+pub struct ToggleAiEvent;
+pub struct NextDifficultyEvent;
\ No newline at end of file
@@ -1,6 +1,12 @@
-use std::time::Duration;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::RangeInclusive,
+    time::Duration,
+};
 
 use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 
 mod events;
 pub use events::*;
@@ -11,9 +17,350 @@ pub use tags::*;
 mod timers;
 pub use timers::*;
 
-#[derive(Resource)]
+mod atlas;
+pub use atlas::*;
+
+mod theme;
+pub use theme::*;
+
+mod tween;
+pub use tween::*;
+
+mod player;
+pub use player::*;
+
+mod profile;
+pub use profile::*;
+
+mod achievement;
+pub use achievement::*;
+
+mod tutorial;
+pub use tutorial::*;
+
+pub use crate::symmetry::*;
+
+#[derive(Resource, Default)]
 pub struct Board(pub HashMap<CellPosition, Entity>);
 
+// Ultimate tic-tac-toe keeps one Board per big board cell, the Board resource then holds
+// the entities of the small boards themselves
+#[derive(Resource, Default)]
+pub struct UltimateBoard {
+    pub boards: HashMap<CellPosition, Board>,
+    // Won small boards map to the winner, drawn ones to CellState::None
+    pub owners: HashMap<CellPosition, CellState>,
+    // Small board the next move must be made in, None when the player may choose
+    pub forced: Option<CellPosition>,
+}
+
+impl UltimateBoard {
+    pub fn is_playable(&self, sub_board: CellPosition) -> bool {
+        !self.owners.contains_key(&sub_board)
+            && self.forced.map_or(true, |forced| forced == sub_board)
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum Variant {
+    #[default]
+    Classic,
+    Ultimate,
+    // Classic board where each player keeps at most MAX_MARKS marks
+    Disappearing,
+    // Marks drop to the lowest empty cell of the picked column
+    Gravity,
+    // 3D boards shown with their own camera
+    Cube3,
+    Cube4,
+    // Unbounded board that grows around the played cells
+    Infinite,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 7] = [
+        Variant::Classic,
+        Variant::Ultimate,
+        Variant::Disappearing,
+        Variant::Gravity,
+        Variant::Cube3,
+        Variant::Cube4,
+        Variant::Infinite,
+    ];
+
+    pub fn is_cube(&self) -> bool {
+        matches!(self, Variant::Cube3 | Variant::Cube4)
+    }
+
+    // Cube boards and infinite boards can be looked around
+    pub fn has_camera_controls(&self) -> bool {
+        self.is_cube() || *self == Variant::Infinite
+    }
+
+    // Only the classic board is small enough to solve while playing
+    pub fn can_be_solved(&self) -> bool {
+        *self == Variant::Classic
+    }
+
+    // Rows and columns of the board, the big board in ultimate tic-tac-toe
+    // and the initially spawned area of an infinite board
+    pub fn size(&self) -> (i32, i32) {
+        match self {
+            Variant::Gravity => (6, 7),
+            Variant::Infinite => (7, 7),
+            Variant::Cube4 => (4, 4),
+            _ => (3, 3),
+        }
+    }
+
+    // Cell positions are centered on the middle of the board, rows grow downwards
+    pub fn rows(&self) -> RangeInclusive<i32> {
+        let (rows, _) = self.size();
+        centered(rows)
+    }
+
+    pub fn cols(&self) -> RangeInclusive<i32> {
+        let (_, cols) = self.size();
+        centered(cols)
+    }
+
+    // Flat boards only have layer 0
+    pub fn layers(&self) -> RangeInclusive<i32> {
+        match self {
+            Variant::Cube3 => centered(3),
+            Variant::Cube4 => centered(4),
+            _ => 0..=0,
+        }
+    }
+
+    // Marks in a row needed to win
+    pub fn line_length(&self) -> usize {
+        match self {
+            Variant::Gravity | Variant::Cube4 => 4,
+            Variant::Infinite => 5,
+            _ => 3,
+        }
+    }
+}
+
+fn centered(len: i32) -> RangeInclusive<i32> {
+    -(len / 2)..=(len - 1) / 2
+}
+
+// Lowest empty cell of a column, where a mark dropped into it lands
+pub fn landing_cell(
+    variant: Variant,
+    col: i32,
+    state_at: impl Fn(CellPosition) -> CellState,
+) -> Option<CellPosition> {
+    variant.rows().rev()
+        .map(|row| CellPosition { row, col, layer: 0 })
+        .find(|pos| state_at(*pos) == CellState::None)
+}
+
+pub const MAX_MARKS: usize = 3;
+
+// Infinite boards keep at least this many cells around every mark
+pub const INFINITE_MARGIN: i32 = 3;
+
+// Cells an infinite board still lacks around a mark placed at `pos`
+pub fn margin_cells(pos: CellPosition, has_cell: impl Fn(CellPosition) -> bool) -> Vec<CellPosition> {
+    (pos.row - INFINITE_MARGIN..=pos.row + INFINITE_MARGIN)
+        .flat_map(|row| (pos.col - INFINITE_MARGIN..=pos.col + INFINITE_MARGIN).map(move |col| CellPosition { row, col, layer: 0 }))
+        .filter(|cell_pos| !has_cell(*cell_pos))
+        .collect()
+}
+
+// Pan and zoom of an infinite board, applied to the board rather than the camera
+// so the turn text and popups stay in place
+#[derive(Resource)]
+pub struct BoardView {
+    pub offset: Vec2,
+    pub zoom: f32,
+}
+
+impl Default for BoardView {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.,
+        }
+    }
+}
+
+// Who filled a cell and when, the oldest mark vanishes in the disappearing variant
+#[derive(Component, Clone, Copy)]
+pub struct Placement {
+    pub player: CellState,
+    pub order: u32,
+}
+
+// Mark that disappears when `player` places another one
+pub fn vanishing_mark<'a>(
+    placements: impl Iterator<Item = (Entity, &'a Placement)>,
+    player: CellState,
+) -> Option<Entity> {
+    let own: Vec<_> = placements.filter(|(_, placement)| placement.player == player).collect();
+    if own.len() < MAX_MARKS { return None; }
+    own.into_iter()
+        .min_by_key(|(_, placement)| placement.order)
+        .map(|(ent, _)| ent)
+}
+
+#[derive(Resource, Clone, Copy, Default)]
+pub struct Rules {
+    pub variant: Variant,
+    pub misere: bool,
+    pub wild: bool,
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum FirstPlayer {
+    #[default]
+    X,
+    O,
+    Alternate,
+    // Draws fall back to alternating
+    LoserStarts,
+    Random,
+}
+
+impl FirstPlayer {
+    pub const ALL: [FirstPlayer; 5] = [
+        FirstPlayer::X,
+        FirstPlayer::O,
+        FirstPlayer::Alternate,
+        FirstPlayer::LoserStarts,
+        FirstPlayer::Random,
+    ];
+}
+
+#[derive(Resource, Default)]
+pub struct StartSettings {
+    pub first_player: FirstPlayer,
+    // The second player may take over the first player's side after the opening move
+    pub pie_rule: bool,
+}
+
+#[derive(Resource)]
+pub struct Round {
+    pub starter: CellState,
+    // Person who lost the last round, see Seating, None after a draw
+    pub loser: Option<usize>,
+    pub swapped: bool,
+}
+
+impl Default for Round {
+    fn default() -> Self {
+        Self {
+            starter: CellState::X,
+            loser: None,
+            swapped: false,
+        }
+    }
+}
+
+// Marks played by the two people at the board, the first of them started out as X.
+// Taking up the pie rule swaps them, so results are kept per person instead of per mark.
+#[derive(Resource)]
+pub struct Seating {
+    pub marks: [CellState; 2],
+}
+
+impl Default for Seating {
+    fn default() -> Self {
+        Self {
+            marks: [CellState::X, CellState::O],
+        }
+    }
+}
+
+impl Seating {
+    // None for CellState::None
+    pub fn person(&self, mark: CellState) -> Option<usize> {
+        self.marks.iter().position(|person_mark| *person_mark == mark)
+    }
+
+    pub fn swap(&mut self) {
+        self.marks.swap(0, 1);
+    }
+}
+
+// Mark the side to move will place in wild tic-tac-toe
+#[derive(Resource)]
+pub struct MarkChoice(pub CellState);
+
+impl Default for MarkChoice {
+    fn default() -> Self {
+        Self(CellState::X)
+    }
+}
+
+// Budget of the Monte Carlo tree search used on boards too big for minimax,
+// the search stops at whichever limit it reaches first
+#[derive(Resource, Clone)]
+pub struct MctsSettings {
+    pub iterations: u32,
+    pub time_budget: Duration,
+    // Higher values spread the playouts over more moves instead of the best looking ones
+    pub exploration: f32,
+}
+
+impl Default for MctsSettings {
+    fn default() -> Self {
+        Self {
+            iterations: 20_000,
+            time_budget: Duration::from_millis(1500),
+            exploration: std::f32::consts::SQRT_2,
+        }
+    }
+}
+
+// How well the computer plays, each level is rated under its own profile
+#[derive(Resource, Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    #[default]
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|difficulty| difficulty == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // Chance of searching a move properly, the other moves only look one move ahead
+    pub fn search_chance(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.,
+            Difficulty::Medium => 0.7,
+            Difficulty::Hard => 1.,
+        }
+    }
+
+    // Share of the tree search budget a searched move gets
+    pub fn mcts_settings(&self, settings: &MctsSettings) -> MctsSettings {
+        let divisor = if *self == Difficulty::Hard { 1 } else { 10 };
+        MctsSettings {
+            iterations: settings.iterations / divisor,
+            time_budget: settings.time_budget / divisor,
+            ..settings.clone()
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, States, Hash, Default, Clone)]
 pub enum GameState {
     #[default]
@@ -22,22 +369,200 @@ pub enum GameState {
     GameOver,
 }
 
-#[derive(Component, Reflect, Eq, PartialEq, Clone, Copy, Debug)]
+impl GameState {
+    pub fn turn_of(mark: CellState) -> GameState {
+        match mark {
+            CellState::X => GameState::XTurn,
+            CellState::O => GameState::OTurn,
+            CellState::None => GameState::GameOver,
+        }
+    }
+
+    pub fn turn_mark(&self) -> CellState {
+        match self {
+            GameState::XTurn => CellState::X,
+            GameState::OTurn => CellState::O,
+            GameState::GameOver => CellState::None,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Eq, PartialEq, Hash, Clone, Copy, Debug)]
 pub enum CellState {
     None,
     X,
     O,
 }
 
-#[derive(Component, Reflect, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+impl CellState {
+    pub fn opponent(&self) -> CellState {
+        match self {
+            CellState::X => CellState::O,
+            CellState::O => CellState::X,
+            CellState::None => CellState::None,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct CellPosition {
     pub row: i32,
     pub col: i32,
+    // Depth into the cube, always 0 on flat boards
+    pub layer: i32,
 }
 
+impl CellPosition {
+    // Even sized boards aren't centered on 0, so symmetries turn around the middle of the
+    // variant's rows. Only square boards map onto themselves, None on the others.
+    // Layers are left alone.
+    pub fn transformed(&self, symmetry: Symmetry, variant: Variant) -> Option<CellPosition> {
+        if variant.rows() != variant.cols() { return None; }
+        let twice_center = variant.rows().start() + variant.rows().end();
+        let (row, col) = symmetry.apply((2 * self.row - twice_center, 2 * self.col - twice_center));
+        Some(CellPosition {
+            row: (row + twice_center) / 2,
+            col: (col + twice_center) / 2,
+            layer: self.layer,
+        })
+    }
+}
+
+// Marks of a board in canonical form, sorted, along with the symmetry that gives it.
+// Rotated or reflected copies of a board have equal forms. Boards that aren't square are
+// only ever in the form they're in, which every symmetry leaves them in here.
+pub fn canonical_marks(
+    variant: Variant,
+    marks: impl IntoIterator<Item = (CellPosition, CellState)>,
+) -> (Vec<(i32, i32, i32, u8)>, Symmetry) {
+    let marks: Vec<_> = marks.into_iter()
+        .filter(|(_, state)| *state != CellState::None)
+        .collect();
+    canonical_form(|symmetry| {
+        let mut form: Vec<_> = marks.iter()
+            .map(|(pos, state)| {
+                let pos = pos.transformed(symmetry, variant).unwrap_or(*pos);
+                (pos.row, pos.col, pos.layer, *state as u8)
+            })
+            .collect();
+        form.sort();
+        form
+    })
+}
+
+// Hash shared by all rotations and reflections of a board
+pub fn canonical_hash(variant: Variant, marks: impl IntoIterator<Item = (CellPosition, CellState)>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    canonical_marks(variant, marks).0.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Position of the small board a cell belongs to in ultimate tic-tac-toe
+#[derive(Component, Reflect, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct SubBoard(pub CellPosition);
+
 #[derive(Component)]
 pub struct TurnText;
 
+#[derive(Component)]
+pub struct ClockText;
+
+// Name and rating of the profile playing a side
+#[derive(Component)]
+pub struct ProfileText(pub CellState);
+
+#[derive(Component)]
+pub struct ProfileAvatar(pub CellState);
+
+// Statistics of both seated profiles, toggled with a key
+#[derive(Component)]
+pub struct ProfileStatsPanel;
+
+#[derive(Component)]
+pub struct ProfileStatsText;
+
+// Toasts are stacked in the order they were shown
+#[derive(Component)]
+pub struct AchievementToast(pub u32);
+
+#[derive(Component)]
+pub struct TutorialTooltip;
+
+#[derive(Component)]
+pub struct TutorialText;
+
+#[derive(Component)]
+pub struct MarkChoiceIndicator;
+
+#[derive(Component)]
+pub struct SwapText;
+
+#[derive(Component)]
+pub struct HintText;
+
+#[derive(Component)]
+pub struct ScoreText;
+
+// Cell currently suggested by a hint
+#[derive(Component)]
+pub struct HintedCell;
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum HintReason {
+    Wins,
+    Blocks,
+    Forks,
+    Best,
+}
+
+impl HintReason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HintReason::Wins => "wins",
+            HintReason::Blocks => "blocks",
+            HintReason::Forks => "forks",
+            HintReason::Best => "best move",
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+// Result of the side to move playing a cell with perfect play from both sides after it
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Evaluation {
+    pub outcome: Outcome,
+    // Moves until the game ends, counting the evaluated one
+    pub moves: u32,
+}
+
+#[derive(Resource, Default)]
+pub struct EvalOverlay {
+    pub enabled: bool,
+    pub evaluations: HashMap<CellPosition, Evaluation>,
+}
+
+#[derive(Component)]
+pub struct EvalLabel;
+
+// Moves of the current game as the side that made them and the flat cell played,
+// see flat_position
+#[derive(Resource, Default)]
+pub struct MoveHistory(pub Vec<(CellState, CellPosition)>);
+
+// Results and hints over all rounds played since the game was started, by person, see Seating
+#[derive(Resource, Default)]
+pub struct MatchScore {
+    pub wins: [u32; 2],
+    pub draws: u32,
+    pub hints: [u32; 2],
+}
+
 #[derive(Component)]
 pub enum GameOverPopup {
     X, O
@@ -50,17 +575,86 @@ pub struct Params {
     pub window_height: f32,
 }
 
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ClockMode {
+    Off,
+    PerMove(Duration),
+    Game(Duration),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum TimeoutRule {
+    RandomMove,
+    Lose,
+}
+
+#[derive(Resource, Clone, Copy)]
+pub struct ClockSettings {
+    pub mode: ClockMode,
+    pub on_timeout: TimeoutRule,
+}
+
+impl ClockSettings {
+    // Cycled through with the clock key
+    pub const PRESETS: [ClockSettings; 3] = [
+        ClockSettings { mode: ClockMode::Off, on_timeout: TimeoutRule::RandomMove },
+        ClockSettings { mode: ClockMode::PerMove(Duration::from_secs(10)), on_timeout: TimeoutRule::RandomMove },
+        ClockSettings { mode: ClockMode::Game(Duration::from_secs(60)), on_timeout: TimeoutRule::Lose },
+    ];
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        Self::PRESETS[0]
+    }
+}
+
 #[derive(Resource)]
 pub struct MaterialHandles {
     pub transparent: Handle<ColorMaterial>,
     pub hovered: Handle<ColorMaterial>,
     pub winner: Handle<ColorMaterial>,
+    pub strike: Handle<ColorMaterial>,
     pub bg: Handle<ColorMaterial>,
+    pub hint: Handle<ColorMaterial>,
+}
+
+// Cube cells are 3D meshes and show their state through these
+#[derive(Resource)]
+pub struct CubeMaterials {
+    pub empty: Handle<StandardMaterial>,
+    pub hovered: Handle<StandardMaterial>,
+    pub winner: Handle<StandardMaterial>,
+    pub x: Handle<StandardMaterial>,
+    pub o: Handle<StandardMaterial>,
+}
+
+#[derive(Component)]
+pub struct OrbitCamera {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub radius: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.6,
+            pitch: 0.45,
+            radius: 7.,
+        }
+    }
 }
 
 #[derive(Resource)]
 pub struct TextureAtlasHandle(pub Handle<TextureAtlas>);
 
+#[derive(Resource)]
+pub struct FontHandle(pub Handle<Font>);
+
+#[derive(Resource)]
+pub struct TextColor(pub Color);
+
 #[derive(Resource)]
 pub struct TextureAtlasIndices {
     pub bg: usize,
@@ -73,4 +667,83 @@ pub struct TextureAtlasIndices {
     pub o_text_bg: usize,
     pub play_btn: usize,
     pub quit_btn: usize,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_land_on_the_lowest_empty_cell() {
+        let bottom = *Variant::Gravity.rows().end();
+        let top = *Variant::Gravity.rows().start();
+        assert_eq!(landing_cell(Variant::Gravity, 1, |_| CellState::None), Some(CellPosition { row: bottom, col: 1, layer: 0 }));
+
+        let filled_up_to = bottom - 2;
+        let state_at = |pos: CellPosition| if pos.row >= filled_up_to { CellState::X } else { CellState::None };
+        assert_eq!(landing_cell(Variant::Gravity, 1, state_at), Some(CellPosition { row: filled_up_to - 1, col: 1, layer: 0 }));
+
+        let state_at = |pos: CellPosition| if pos.row >= top { CellState::O } else { CellState::None };
+        assert_eq!(landing_cell(Variant::Gravity, 1, state_at), None);
+    }
+
+    #[test]
+    fn infinite_boards_keep_a_margin_around_marks() {
+        let pos = CellPosition { row: 0, col: 3, layer: 0 };
+        let size = 2 * INFINITE_MARGIN as usize + 1;
+        assert_eq!(margin_cells(pos, |_| false).len(), size * size);
+
+        // Only the columns past the edge of a board spanning -3..=3 are missing
+        let missing = margin_cells(pos, |cell_pos| cell_pos.col.abs() <= 3);
+        assert_eq!(missing.len(), 3 * size);
+        assert!(missing.iter().all(|cell_pos| (4..=6).contains(&cell_pos.col)));
+    }
+
+    #[test]
+    fn transformed_is_undone_by_the_inverse() {
+        for variant in [Variant::Classic, Variant::Cube4, Variant::Infinite] {
+            for symmetry in Symmetry::ALL {
+                for row in variant.rows() {
+                    for col in variant.cols() {
+                        let pos = CellPosition { row, col, layer: 0 };
+                        let transformed = pos.transformed(symmetry, variant).unwrap();
+                        assert!(variant.rows().contains(&transformed.row) && variant.cols().contains(&transformed.col));
+                        assert_eq!(transformed.transformed(symmetry.inverse(), variant), Some(pos), "{:?} {:?}", variant, symmetry);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transformed_matches_apply_index() {
+        for (variant, size) in [(Variant::Classic, 3), (Variant::Cube4, 4)] {
+            let first = *variant.rows().start();
+            let position = |index: usize| CellPosition {
+                row: first + (index / size) as i32,
+                col: first + (index % size) as i32,
+                layer: 0,
+            };
+            for symmetry in Symmetry::ALL {
+                for index in 0..size * size {
+                    assert_eq!(
+                        position(index).transformed(symmetry, variant),
+                        Some(position(symmetry.apply_index(index, size))),
+                        "{:?} {:?} {}", variant, symmetry, index,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boards_that_are_not_square_are_left_as_they_are() {
+        let pos = CellPosition { row: 0, col: 1, layer: 0 };
+        assert_eq!(pos.transformed(Symmetry::Rotate90, Variant::Gravity), None);
+
+        let marks = [(pos, CellState::X), (CellPosition { row: 2, col: -3, layer: 0 }, CellState::O)];
+        let (form, symmetry) = canonical_marks(Variant::Gravity, marks);
+        assert_eq!(form, vec![(0, 1, 0, CellState::X as u8), (2, -3, 0, CellState::O as u8)]);
+        assert_eq!(symmetry, Symmetry::Identity);
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{CellPosition, CellState, Rules};
+
+// Cell and the mark placed on it
+pub type Move = (CellPosition, CellState);
+
+// The board as a controller sees it, detached from the world so it can be sent anywhere.
+// Ultimate boards are flattened onto one 9x9 grid, see flat_position.
+#[derive(Clone, Default)]
+pub struct Position {
+    pub cells: HashMap<CellPosition, CellState>,
+    pub legal: Vec<CellPosition>,
+    // Mark each side loses with its next move, only with disappearing marks
+    pub vanishing: HashMap<CellState, CellPosition>,
+}
+
+impl Position {
+    pub fn state_at(&self, pos: CellPosition) -> CellState {
+        self.cells.get(&pos).copied().unwrap_or(CellState::None)
+    }
+}
+
+pub fn flat_position(pos: CellPosition, sub_board: Option<CellPosition>) -> CellPosition {
+    match sub_board {
+        Some(sub_board) => CellPosition {
+            row: sub_board.row * 3 + pos.row,
+            col: sub_board.col * 3 + pos.col,
+            layer: pos.layer,
+        },
+        None => pos,
+    }
+}
+
+// Small board and position within it of a flat ultimate position, undoes flat_position
+pub fn split_position(flat_pos: CellPosition) -> (CellPosition, CellPosition) {
+    let sub_board = CellPosition {
+        row: (flat_pos.row + 1).div_euclid(3),
+        col: (flat_pos.col + 1).div_euclid(3),
+        layer: 0,
+    };
+    let pos = CellPosition {
+        row: flat_pos.row - sub_board.row * 3,
+        col: flat_pos.col - sub_board.col * 3,
+        layer: flat_pos.layer,
+    };
+    (sub_board, pos)
+}
+
+pub struct MoveRequest<'a> {
+    // Side to move
+    pub player: CellState,
+    pub rules: Rules,
+    pub position: &'a Position,
+    // Cell the local player clicked since the last request, with the mark they chose
+    pub local_move: Option<Move>,
+    // Time since the last request
+    pub delta: Duration,
+}
+
+// Plays one side of the game: the local player, the computer, or anything else that can come
+// up with moves. The controller of the side to move is asked every frame until it answers,
+// so slow controllers return None until they are done.
+pub trait Controller: Send + Sync {
+    // Whether the side is played with this machine's mouse and keyboard
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    // Profile rated for the side's games, None for the local profile seated at it
+    fn profile(&self) -> Option<String> {
+        None
+    }
+
+    fn new_game(&mut self) {}
+
+    fn start_turn(&mut self) {}
+
+    fn poll_move(&mut self, request: &MoveRequest) -> Option<Move>;
+}
+
+#[derive(Resource)]
+pub struct Players {
+    pub x: Box<dyn Controller>,
+    pub o: Box<dyn Controller>,
+}
+
+impl Players {
+    pub fn get(&self, side: CellState) -> &dyn Controller {
+        if side == CellState::O { &*self.o } else { &*self.x }
+    }
+
+    pub fn get_mut(&mut self, side: CellState) -> &mut dyn Controller {
+        if side == CellState::O { &mut *self.o } else { &mut *self.x }
+    }
+
+    pub fn is_human(&self, side: CellState) -> bool {
+        self.get(side).is_human()
+    }
+}
@@ -0,0 +1,292 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Achievement, CellPosition, CellState, Difficulty, Outcome, Variant};
+
+pub const PROFILES_PATH: &str = "profiles.ron";
+
+pub const INITIAL_RATING: f32 = 1200.;
+
+// Rating points at stake in one game
+const K_FACTOR: f32 = 32.;
+
+// Profiles of whoever plays the computer's side, rated like everyone else
+// with one profile for each difficulty
+pub const COMPUTER_PROFILE: &str = "Computer";
+
+pub fn computer_profile(difficulty: Difficulty) -> String {
+    format!("{} ({})", COMPUTER_PROFILE, difficulty.label())
+}
+
+const ENGINE_PROFILE_PREFIX: &str = "engine ";
+
+pub fn engine_profile(command: &str) -> String {
+    format!("{}{}", ENGINE_PROFILE_PREFIX, command)
+}
+
+// Tints a profile's marks can be drawn with, white keeps the theme's colors
+pub const MARK_COLORS: [&str; 6] = ["#ffffff", "#ff6b6b", "#4fa3a5", "#f6c945", "#8bd450", "#b28dff"];
+
+// Atlas sprite shown next to a profile's name
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum Avatar {
+    #[default]
+    Cross,
+    Ring,
+}
+
+impl Avatar {
+    pub const ALL: [Avatar; 2] = [Avatar::Cross, Avatar::Ring];
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameRecord {
+    pub opponent: String,
+    pub variant: Variant,
+    pub outcome: Outcome,
+    // Rating once the game was rated
+    pub rating: f32,
+    // Moves played by both sides
+    #[serde(default)]
+    pub moves: u32,
+    // First cell the profile played, flat like in MoveHistory
+    #[serde(default)]
+    pub opening: Option<CellPosition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    // One of MARK_COLORS
+    #[serde(default = "default_mark_color")]
+    pub color: String,
+    #[serde(default)]
+    pub avatar: Avatar,
+    // In the order they were unlocked
+    #[serde(default)]
+    pub achievements: Vec<Achievement>,
+    // Oldest game first
+    pub history: Vec<GameRecord>,
+}
+
+fn default_mark_color() -> String {
+    MARK_COLORS[0].to_string()
+}
+
+pub struct ProfileStats {
+    pub games: usize,
+    pub win_rate: f32,
+    pub longest_streak: usize,
+    // Moves per game, counting both sides
+    pub average_length: f32,
+    pub favorite_opening: Option<CellPosition>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            color: default_mark_color(),
+            avatar: Avatar::default(),
+            achievements: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    // Profiles the computer and engines are rated under can't be seated
+    pub fn is_local(&self) -> bool {
+        !self.name.starts_with(COMPUTER_PROFILE) && !self.name.starts_with(ENGINE_PROFILE_PREFIX)
+    }
+
+    // Each variant is rated on its own, from the rating the last game of it left
+    pub fn rating(&self, variant: Variant) -> f32 {
+        self.history.iter().rev()
+            .find(|record| record.variant == variant)
+            .map_or(INITIAL_RATING, |record| record.rating)
+    }
+
+    pub fn mark_color(&self) -> Color {
+        Color::hex(self.color.trim_start_matches('#')).unwrap_or(Color::WHITE)
+    }
+
+    pub fn stats(&self) -> ProfileStats {
+        let games = self.history.len();
+        let wins = self.history.iter().filter(|record| record.outcome == Outcome::Win).count();
+
+        let mut streak = 0;
+        let mut longest_streak = 0;
+        for record in self.history.iter() {
+            streak = if record.outcome == Outcome::Win { streak + 1 } else { 0 };
+            longest_streak = longest_streak.max(streak);
+        }
+
+        let mut openings: Vec<(CellPosition, usize)> = Vec::new();
+        for opening in self.history.iter().filter_map(|record| record.opening) {
+            match openings.iter_mut().find(|(pos, _)| *pos == opening) {
+                Some((_, count)) => *count += 1,
+                None => openings.push((opening, 1)),
+            }
+        }
+        // max_by_key keeps the last of equal counts, so ties go to the square first played
+        let favorite_opening = openings.iter().rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(pos, _)| *pos);
+
+        let total_moves: u32 = self.history.iter().map(|record| record.moves).sum();
+        ProfileStats {
+            games,
+            win_rate: if games == 0 { 0. } else { wins as f32 / games as f32 },
+            longest_streak,
+            average_length: if games == 0 { 0. } else { total_moves as f32 / games as f32 },
+            favorite_opening,
+        }
+    }
+}
+
+// Score a player rated `rating` is expected to take from a game against `opponent_rating`,
+// 1 for a win and 0.5 for a draw
+pub fn expected_score(rating: f32, opponent_rating: f32) -> f32 {
+    1. / (1. + 10f32.powf((opponent_rating - rating) / 400.))
+}
+
+// Elo rating change for a game ending in `outcome`
+pub fn rating_change(rating: f32, opponent_rating: f32, outcome: Outcome) -> f32 {
+    let score = match outcome {
+        Outcome::Win => 1.,
+        Outcome::Draw => 0.5,
+        Outcome::Loss => 0.,
+    };
+    K_FACTOR * (score - expected_score(rating, opponent_rating))
+}
+
+// Local profiles, saved to PROFILES_PATH whenever they change
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct Profiles {
+    pub profiles: Vec<Profile>,
+    // Profiles seated at each side when it's played on this machine
+    pub x: String,
+    pub o: String,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self {
+            profiles: vec![Profile::new("Player 1"), Profile::new("Player 2")],
+            x: "Player 1".to_string(),
+            o: "Player 2".to_string(),
+        }
+    }
+}
+
+impl Profiles {
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn get_or_create(&mut self, name: &str) -> &mut Profile {
+        match self.profiles.iter().position(|profile| profile.name == name) {
+            Some(index) => &mut self.profiles[index],
+            None => {
+                self.profiles.push(Profile::new(name));
+                self.profiles.last_mut().unwrap()
+            },
+        }
+    }
+
+    pub fn has_unlocked(&self, achievement: Achievement) -> bool {
+        self.profiles.iter().any(|profile| profile.achievements.contains(&achievement))
+    }
+
+    pub fn seat(&self, side: CellState) -> &str {
+        if side == CellState::O { &self.o } else { &self.x }
+    }
+
+    pub fn seat_mut(&mut self, side: CellState) -> &mut String {
+        if side == CellState::O { &mut self.o } else { &mut self.x }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(outcome: Outcome, moves: u32, opening: Option<(i32, i32)>) -> GameRecord {
+        GameRecord {
+            opponent: "Player 2".to_string(),
+            variant: Variant::Classic,
+            outcome,
+            rating: INITIAL_RATING,
+            moves,
+            opening: opening.map(|(row, col)| CellPosition { row, col, layer: 0 }),
+        }
+    }
+
+    #[test]
+    fn rating_changes_are_zero_sum() {
+        assert_eq!(rating_change(INITIAL_RATING, INITIAL_RATING, Outcome::Win), K_FACTOR / 2.);
+        assert_eq!(rating_change(INITIAL_RATING, INITIAL_RATING, Outcome::Draw), 0.);
+        assert_eq!(rating_change(INITIAL_RATING, INITIAL_RATING, Outcome::Loss), -K_FACTOR / 2.);
+
+        for (outcome, opponent_outcome) in [(Outcome::Win, Outcome::Loss), (Outcome::Draw, Outcome::Draw)] {
+            let change = rating_change(1400., 1200., outcome);
+            let opponent_change = rating_change(1200., 1400., opponent_outcome);
+            assert!((change + opponent_change).abs() < 1e-4);
+        }
+        // Beating a weaker player is worth less than beating a stronger one
+        assert!(rating_change(1400., 1200., Outcome::Win) < rating_change(1200., 1400., Outcome::Win));
+        assert!(rating_change(1400., 1200., Outcome::Draw) < 0.);
+    }
+
+    #[test]
+    fn stats_of_a_new_profile_are_empty() {
+        let stats = Profile::new("Player 1").stats();
+        assert_eq!(stats.games, 0);
+        assert_eq!(stats.win_rate, 0.);
+        assert_eq!(stats.longest_streak, 0);
+        assert_eq!(stats.average_length, 0.);
+        assert_eq!(stats.favorite_opening, None);
+    }
+
+    #[test]
+    fn stats_summarize_the_history() {
+        let mut profile = Profile::new("Player 1");
+        profile.history = vec![
+            record(Outcome::Win, 5, Some((0, 0))),
+            record(Outcome::Loss, 6, Some((-1, -1))),
+            record(Outcome::Win, 7, Some((-1, -1))),
+            record(Outcome::Win, 5, Some((0, 0))),
+            record(Outcome::Win, 9, None),
+            record(Outcome::Draw, 8, Some((1, 1))),
+        ];
+        let stats = profile.stats();
+        assert_eq!(stats.games, 6);
+        assert_eq!(stats.win_rate, 4. / 6.);
+        assert_eq!(stats.longest_streak, 3);
+        assert_eq!(stats.average_length, 40. / 6.);
+        // Tied with the corner, but played first
+        assert_eq!(stats.favorite_opening, Some(CellPosition { row: 0, col: 0, layer: 0 }));
+    }
+
+    #[test]
+    fn variants_are_rated_separately() {
+        let mut profile = Profile::new("Player 1");
+        assert_eq!(profile.rating(Variant::Classic), INITIAL_RATING);
+        profile.history = vec![
+            GameRecord { rating: 1216., ..record(Outcome::Win, 5, None) },
+            GameRecord { variant: Variant::Gravity, rating: 1184., ..record(Outcome::Loss, 20, None) },
+            GameRecord { rating: 1230., ..record(Outcome::Win, 7, None) },
+        ];
+        assert_eq!(profile.rating(Variant::Classic), 1230.);
+        assert_eq!(profile.rating(Variant::Gravity), 1184.);
+        assert_eq!(profile.rating(Variant::Ultimate), INITIAL_RATING);
+    }
+
+    #[test]
+    fn computer_profiles_are_not_local() {
+        for difficulty in Difficulty::ALL {
+            assert!(!Profile::new(&computer_profile(difficulty)).is_local());
+        }
+        assert!(!Profile::new(&engine_profile("./engine")).is_local());
+        assert!(Profile::new("Player 1").is_local());
+    }
+}
@@ -4,4 +4,19 @@ use bevy::prelude::Component;
 pub struct PlayBtn;
 
 #[derive(Component)]
-pub struct QuitBtn;
\ No newline at end of file
+pub struct QuitBtn;
+
+#[derive(Component)]
+pub struct HintBtn;
+
+#[derive(Component)]
+pub struct MarkSprite;
+
+#[derive(Component)]
+pub struct BoardRoot;
+
+#[derive(Component)]
+pub struct CubeCell;
+
+#[derive(Component)]
+pub struct DrawText;
\ No newline at end of file
@@ -0,0 +1,53 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use super::{Achievement, AtlasManifest};
+
+#[derive(TypeUuid)]
+#[uuid = "5b0e3a52-8f0c-4a53-9a8e-3f1c7d0b6e21"]
+pub struct Theme {
+    pub name: String,
+    pub palette: Palette,
+    pub atlas: Handle<AtlasManifest>,
+}
+
+pub struct Palette {
+    pub hovered: Color,
+    pub winner: Color,
+    pub strike: Color,
+    pub bg: Color,
+    pub hint: Color,
+    pub text: Color,
+}
+
+// Theme files as they are written on disk, colors are hex strings
+#[derive(Deserialize)]
+pub struct ThemeFile {
+    pub name: String,
+    pub palette: PaletteFile,
+    pub atlas: String,
+}
+
+#[derive(Deserialize)]
+pub struct PaletteFile {
+    pub hovered: String,
+    pub winner: String,
+    pub strike: String,
+    pub bg: String,
+    pub hint: String,
+    pub text: String,
+}
+
+#[derive(Resource)]
+pub struct Themes {
+    pub handles: Vec<Handle<Theme>>,
+    // Achievement unlocking each theme, None for themes that are always available
+    pub locked_by: Vec<Option<Achievement>>,
+    pub current: usize,
+}
+
+impl Themes {
+    pub fn current_handle(&self) -> &Handle<Theme> {
+        &self.handles[self.current]
+    }
+}
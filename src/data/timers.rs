@@ -2,6 +2,8 @@ use std::time::Duration;
 
 use bevy::prelude::*;
 
+use super::CellState;
+
 #[derive(Component)]
 pub struct BlinkingTimer {
     timer: Timer,
@@ -31,4 +33,68 @@ impl BlinkingTimer {
 }
 
 #[derive(Component)]
-pub struct DelayTimer(pub Timer);
\ No newline at end of file
+pub struct DelayTimer(pub Timer);
+
+// Game over sequence: the winning cells blink, then the line is struck through them,
+// then the popup shows up once the line has finished growing
+pub const STRIKE_DELAY_MS: u64 = 500;
+pub const STRIKE_GROW_MS: u64 = 300;
+pub const POPUP_DELAY_MS: u64 = STRIKE_DELAY_MS + STRIKE_GROW_MS + 400;
+
+#[derive(Component)]
+pub struct StrikeThrough {
+    pub from: Vec3,
+    pub to: Vec3,
+    pub thickness: f32,
+    delay: Timer,
+    grow: Timer,
+}
+
+impl StrikeThrough {
+    pub fn new(from: Vec3, to: Vec3, thickness: f32) -> Self {
+        Self {
+            from,
+            to,
+            thickness,
+            delay: Timer::new(Duration::from_millis(STRIKE_DELAY_MS), TimerMode::Once),
+            grow: Timer::new(Duration::from_millis(STRIKE_GROW_MS), TimerMode::Once),
+        }
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        if self.delay.finished() {
+            self.grow.tick(delta);
+        } else {
+            self.delay.tick(delta);
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.grow.percent()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.grow.finished()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TurnClock {
+    pub x: Timer,
+    pub o: Timer,
+}
+
+impl TurnClock {
+    pub fn reset(&mut self, length: Duration) {
+        self.x = Timer::new(length, TimerMode::Once);
+        self.o = Timer::new(length, TimerMode::Once);
+    }
+
+    pub fn get_mut(&mut self, mark: CellState) -> Option<&mut Timer> {
+        match mark {
+            CellState::X => Some(&mut self.x),
+            CellState::O => Some(&mut self.o),
+            CellState::None => None,
+        }
+    }
+}
\ No newline at end of file
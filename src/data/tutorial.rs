@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use super::{CellPosition, Players};
+
+// Lesson of the tutorial, played on a classic board with X to move. Cells are row, col.
+pub struct TutorialStep {
+    pub title: &'static str,
+    pub text: &'static str,
+    pub x: &'static [(i32, i32)],
+    pub o: &'static [(i32, i32)],
+    // Cells X may play, any empty cell when None
+    pub allowed: Option<&'static [(i32, i32)]>,
+    // Cells the lesson is about, playing one of them moves on to the next lesson
+    pub intended: &'static [(i32, i32)],
+    pub success: &'static str,
+    // Shown for allowed cells that aren't intended, the lesson is then tried again
+    pub mistake: &'static str,
+}
+
+pub const TUTORIAL: [TutorialStep; 5] = [
+    TutorialStep {
+        title: "Three in a row",
+        text: "Players take turns placing marks on empty cells. Three in a row, column or diagonal wins. Finish the top row.",
+        x: &[(-1, -1), (-1, 0)],
+        o: &[(0, -1), (1, 1)],
+        allowed: Some(&[(-1, 1)]),
+        intended: &[(-1, 1)],
+        success: "That's a line, X wins!",
+        mistake: "",
+    },
+    TutorialStep {
+        title: "Take the center",
+        text: "The center is part of four lines, more than any other cell. Open the game in the center.",
+        x: &[],
+        o: &[],
+        allowed: None,
+        intended: &[(0, 0)],
+        success: "Every line through the center is now yours to build.",
+        mistake: "Only the center sits on four lines, try again.",
+    },
+    TutorialStep {
+        title: "Corners",
+        text: "O took the center. Answer in a corner, an edge lets O set up a fork.",
+        x: &[],
+        o: &[(0, 0)],
+        allowed: None,
+        intended: &[(-1, -1), (-1, 1), (1, -1), (1, 1)],
+        success: "A corner keeps you safe against the center.",
+        mistake: "Edges lose against the center with good play, pick a corner.",
+    },
+    TutorialStep {
+        title: "Blocking",
+        text: "O has two in a row. Block the line before O completes it.",
+        x: &[(-1, -1), (1, 0)],
+        o: &[(0, 0), (0, 1)],
+        allowed: None,
+        intended: &[(0, -1)],
+        success: "Blocked, O has to start over.",
+        mistake: "O wins next move on the middle row, block it.",
+    },
+    TutorialStep {
+        title: "Forks",
+        text: "A fork makes two lines of two at once, only one of them can be blocked. Find the fork.",
+        x: &[(-1, -1), (-1, 1)],
+        o: &[(-1, 0), (1, 1)],
+        allowed: None,
+        intended: &[(1, -1)],
+        success: "Two threats, O can only block one of them.",
+        mistake: "That makes one threat at most, look for a cell on two open lines.",
+    },
+];
+
+pub fn tutorial_cell((row, col): (i32, i32)) -> CellPosition {
+    CellPosition { row, col, layer: 0 }
+}
+
+#[derive(Resource, Default)]
+pub struct Tutorial {
+    // Lesson being taught, None outside the tutorial
+    pub step: Option<usize>,
+    // The lesson's position still has to be put on the board
+    pub loading: bool,
+    // The board was cleared for the lesson, it's loaded once the board's cells exist
+    pub board_cleared: bool,
+    // Who played each side before the tutorial, given back when it ends
+    pub previous_players: Option<Players>,
+    pub allowed: Vec<CellPosition>,
+    // Whether the lesson's move was the intended one, shown until the timer finishes
+    pub feedback: Option<(bool, Timer)>,
+    // The player clicked a cell the lesson doesn't allow
+    pub blocked_click: bool,
+}
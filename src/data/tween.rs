@@ -0,0 +1,97 @@
+use std::{f32::consts::PI, time::Duration};
+
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    CubicOut,
+    BackOut,
+}
+
+impl Ease {
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1. - (1. - t) * (1. - t),
+            Ease::CubicOut => 1. - (1. - t).powi(3),
+            Ease::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.;
+                1. + c3 * (t - 1.).powi(3) + c1 * (t - 1.).powi(2)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TweenTarget {
+    Scale { from: Vec3, to: Vec3 },
+    Translation { from: Vec3, to: Vec3 },
+    // Applies to sprite and text colors
+    Alpha { from: f32, to: f32 },
+}
+
+#[derive(Component)]
+pub struct Tween {
+    pub target: TweenTarget,
+    pub ease: Ease,
+    yoyo: bool,
+    despawn_on_finish: bool,
+    delay: Timer,
+    timer: Timer,
+}
+
+impl Tween {
+    pub fn new(target: TweenTarget, length: Duration, ease: Ease) -> Self {
+        Self {
+            target,
+            ease,
+            yoyo: false,
+            despawn_on_finish: false,
+            delay: Timer::new(Duration::ZERO, TimerMode::Once),
+            timer: Timer::new(length, TimerMode::Once),
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Timer::new(delay, TimerMode::Once);
+        self
+    }
+
+    // Goes to the target and back again within the tween's length
+    pub fn with_yoyo(mut self) -> Self {
+        self.yoyo = true;
+        self
+    }
+
+    pub fn with_despawn(mut self) -> Self {
+        self.despawn_on_finish = true;
+        self
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        if self.delay.finished() {
+            self.timer.tick(delta);
+        } else {
+            self.delay.tick(delta);
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        let t = self.timer.percent();
+        let t = if self.yoyo { (t * PI).sin() } else { t };
+        self.ease.apply(t)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.timer.finished()
+    }
+
+    pub fn despawns(&self) -> bool {
+        self.despawn_on_finish
+    }
+}
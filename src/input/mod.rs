@@ -1,4 +1,4 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, input::mouse::{MouseMotion, MouseWheel}};
 use bevy_mod_picking::{PickingEvent, HoverEvent};
 use crate::data::*;
 
@@ -8,80 +8,310 @@ impl Plugin for InputPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
             .add_event::<CellPickedEvent>()
+            .add_event::<LocalMoveEvent>()
             .add_event::<PlayBtnClickedEvt>()
             .add_event::<QuitBtnClickedEvt>()
-            .add_systems((handle_cell_hover, handle_cell_picking).in_set(OnUpdate(GameState::XTurn)))
-            .add_systems((handle_cell_hover, handle_cell_picking).in_set(OnUpdate(GameState::OTurn)));
-            //.add_system(handle_play_btn_clicked);
+            .add_event::<NextClockModeEvent>()
+            .add_event::<NextVariantEvent>()
+            .add_event::<ToggleMisereEvent>()
+            .add_event::<ToggleAiEvent>()
+            .add_event::<NextDifficultyEvent>()
+            .add_event::<ToggleWildEvent>()
+            .add_event::<SwapMarkChoiceEvent>()
+            .add_event::<NextFirstPlayerEvent>()
+            .add_event::<TogglePieRuleEvent>()
+            .add_event::<SwapSidesEvent>()
+            .add_event::<HintRequestedEvent>()
+            .add_event::<ToggleEvalOverlayEvent>()
+            .add_event::<ToggleProfileStatsEvent>()
+            .add_event::<ToggleTutorialEvent>()
+            .add_systems((handle_cell_hover, handle_cell_picking, handle_hint_input).in_set(OnUpdate(GameState::XTurn)))
+            .add_systems((handle_cell_hover, handle_cell_picking, handle_hint_input).in_set(OnUpdate(GameState::OTurn)))
+            .add_system(handle_theme_key)
+            .add_system(handle_clock_key)
+            .add_system(handle_variant_key)
+            .add_system(handle_misere_key)
+            .add_system(handle_ai_key)
+            .add_system(handle_wild_key)
+            .add_system(handle_start_keys)
+            .add_system(handle_eval_overlay_key)
+            .add_system(handle_profile_keys)
+            .add_system(handle_tutorial_key)
+            .add_system(handle_mark_choice_input)
+            .add_system(handle_orbit_input)
+            .add_system(handle_pan_input)
+            .add_system(handle_popup_btn_clicked.in_set(OnUpdate(GameState::GameOver)));
     }
 }
 
-fn handle_play_btn_clicked(
+fn handle_popup_btn_clicked(
     mut picking_evt_rdr: EventReader<PickingEvent>,
     mut play_btn_evt_wtr: EventWriter<PlayBtnClickedEvt>,
-    game_over_popup_qry: Query<(Entity, With<GameOverPopup>)>,
+    mut quit_btn_evt_wtr: EventWriter<QuitBtnClickedEvt>,
     play_btn_qry: Query<&PlayBtn>,
-    name_qry: Query<&Name>,
+    quit_btn_qry: Query<&QuitBtn>,
 ) {
     picking_evt_rdr.iter().for_each(|evt| {
         match evt {
-            PickingEvent::Clicked(ety) => {
-                // FIXME: no entities detected with the PlayBtn component
-                // maybe because it's a child of an the game over popup entity?
-                if let Ok(name) = name_qry.get(*ety) {
-                    println!("{}", name);
-                }
-                
-                /*
-                if let Ok(_) = play_btn_qry.get(*ent) {
+            PickingEvent::Clicked(ent) => {
+                if play_btn_qry.contains(*ent) {
                     play_btn_evt_wtr.send(PlayBtnClickedEvt);
                 }
-                */
+                if quit_btn_qry.contains(*ent) {
+                    quit_btn_evt_wtr.send(QuitBtnClickedEvt);
+                }
             }
             _ => ()
         }
     });
 }
 
+fn handle_theme_key(
+    keys: Res<Input<KeyCode>>,
+    mut next_theme_evt_wtr: EventWriter<NextThemeEvent>,
+) {
+    if keys.just_pressed(KeyCode::T) {
+        next_theme_evt_wtr.send(NextThemeEvent);
+    }
+}
+
+fn handle_clock_key(
+    keys: Res<Input<KeyCode>>,
+    mut next_clock_mode_evt_wtr: EventWriter<NextClockModeEvent>,
+) {
+    if keys.just_pressed(KeyCode::C) {
+        next_clock_mode_evt_wtr.send(NextClockModeEvent);
+    }
+}
+
+fn handle_variant_key(
+    keys: Res<Input<KeyCode>>,
+    mut next_variant_evt_wtr: EventWriter<NextVariantEvent>,
+) {
+    if keys.just_pressed(KeyCode::V) {
+        next_variant_evt_wtr.send(NextVariantEvent);
+    }
+}
+
+fn handle_misere_key(
+    keys: Res<Input<KeyCode>>,
+    mut toggle_misere_evt_wtr: EventWriter<ToggleMisereEvent>,
+) {
+    if keys.just_pressed(KeyCode::M) {
+        toggle_misere_evt_wtr.send(ToggleMisereEvent);
+    }
+}
+
+// A toggles the computer and D cycles how well it plays
+fn handle_ai_key(
+    keys: Res<Input<KeyCode>>,
+    mut toggle_ai_evt_wtr: EventWriter<ToggleAiEvent>,
+    mut next_difficulty_evt_wtr: EventWriter<NextDifficultyEvent>,
+) {
+    if keys.just_pressed(KeyCode::A) {
+        toggle_ai_evt_wtr.send(ToggleAiEvent);
+    }
+    if keys.just_pressed(KeyCode::D) {
+        next_difficulty_evt_wtr.send(NextDifficultyEvent);
+    }
+}
+
+fn handle_wild_key(
+    keys: Res<Input<KeyCode>>,
+    mut toggle_wild_evt_wtr: EventWriter<ToggleWildEvent>,
+) {
+    if keys.just_pressed(KeyCode::W) {
+        toggle_wild_evt_wtr.send(ToggleWildEvent);
+    }
+}
+
+// F cycles who starts, P toggles the pie rule and S takes it up
+fn handle_start_keys(
+    keys: Res<Input<KeyCode>>,
+    mut next_first_player_evt_wtr: EventWriter<NextFirstPlayerEvent>,
+    mut toggle_pie_rule_evt_wtr: EventWriter<TogglePieRuleEvent>,
+    mut swap_sides_evt_wtr: EventWriter<SwapSidesEvent>,
+) {
+    if keys.just_pressed(KeyCode::F) {
+        next_first_player_evt_wtr.send(NextFirstPlayerEvent);
+    }
+    if keys.just_pressed(KeyCode::P) {
+        toggle_pie_rule_evt_wtr.send(TogglePieRuleEvent);
+    }
+    if keys.just_pressed(KeyCode::S) {
+        swap_sides_evt_wtr.send(SwapSidesEvent);
+    }
+}
+
+fn handle_eval_overlay_key(
+    keys: Res<Input<KeyCode>>,
+    mut toggle_eval_overlay_evt_wtr: EventWriter<ToggleEvalOverlayEvent>,
+) {
+    if keys.just_pressed(KeyCode::E) {
+        toggle_eval_overlay_evt_wtr.send(ToggleEvalOverlayEvent);
+    }
+}
+
+fn handle_tutorial_key(
+    keys: Res<Input<KeyCode>>,
+    mut toggle_tutorial_evt_wtr: EventWriter<ToggleTutorialEvent>,
+) {
+    if keys.just_pressed(KeyCode::L) {
+        toggle_tutorial_evt_wtr.send(ToggleTutorialEvent);
+    }
+}
+
+// 1/2 seat the next profile at X/O, 3/4 change their mark color, 5/6 their avatar,
+// tab shows their statistics
+fn handle_profile_keys(
+    keys: Res<Input<KeyCode>>,
+    mut next_profile_evt_wtr: EventWriter<NextProfileEvent>,
+    mut next_mark_color_evt_wtr: EventWriter<NextMarkColorEvent>,
+    mut next_avatar_evt_wtr: EventWriter<NextAvatarEvent>,
+    mut toggle_profile_stats_evt_wtr: EventWriter<ToggleProfileStatsEvent>,
+) {
+    for (side, profile_key, color_key, avatar_key) in [
+        (CellState::X, KeyCode::Key1, KeyCode::Key3, KeyCode::Key5),
+        (CellState::O, KeyCode::Key2, KeyCode::Key4, KeyCode::Key6),
+    ] {
+        if keys.just_pressed(profile_key) {
+            next_profile_evt_wtr.send(NextProfileEvent(side));
+        }
+        if keys.just_pressed(color_key) {
+            next_mark_color_evt_wtr.send(NextMarkColorEvent(side));
+        }
+        if keys.just_pressed(avatar_key) {
+            next_avatar_evt_wtr.send(NextAvatarEvent(side));
+        }
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        toggle_profile_stats_evt_wtr.send(ToggleProfileStatsEvent);
+    }
+}
+
+fn handle_hint_input(
+    mut picking_evt_rdr: EventReader<PickingEvent>,
+    mut hint_requested_evt_wtr: EventWriter<HintRequestedEvent>,
+    keys: Res<Input<KeyCode>>,
+    hint_btn_qry: Query<&HintBtn>,
+) {
+    let btn_clicked = picking_evt_rdr.iter().any(|evt| {
+        matches!(evt, PickingEvent::Clicked(ent) if hint_btn_qry.contains(*ent))
+    });
+    if btn_clicked || keys.just_pressed(KeyCode::H) {
+        hint_requested_evt_wtr.send(HintRequestedEvent);
+    }
+}
+
+// Space or a right click swaps the mark to place in wild tic-tac-toe,
+// boards with camera controls use the right mouse button for those instead
+fn handle_mark_choice_input(
+    keys: Res<Input<KeyCode>>,
+    mouse_btns: Res<Input<MouseButton>>,
+    rules: Res<Rules>,
+    mut swap_mark_choice_evt_wtr: EventWriter<SwapMarkChoiceEvent>,
+) {
+    let right_click = mouse_btns.just_pressed(MouseButton::Right) && !rules.variant.has_camera_controls();
+    if keys.just_pressed(KeyCode::Space) || right_click {
+        swap_mark_choice_evt_wtr.send(SwapMarkChoiceEvent);
+    }
+}
+
+// Right mouse drags or the arrow keys orbit the cube, the wheel zooms
+fn handle_orbit_input(
+    mut mouse_motion_evt_rdr: EventReader<MouseMotion>,
+    mut mouse_wheel_evt_rdr: EventReader<MouseWheel>,
+    mut orbit_qry: Query<&mut OrbitCamera>,
+    mouse_btns: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    rules: Res<Rules>,
+    time: Res<Time>,
+) {
+    let motion: Vec2 = mouse_motion_evt_rdr.iter().map(|evt| evt.delta).sum();
+    let scroll: f32 = mouse_wheel_evt_rdr.iter().map(|evt| evt.y).sum();
+    if !rules.variant.is_cube() { return; }
+
+    let mut rotation = Vec2::ZERO;
+    if mouse_btns.pressed(MouseButton::Right) {
+        rotation += motion * 0.005;
+    }
+    for (key, dir) in [
+        (KeyCode::Left, Vec2::NEG_X),
+        (KeyCode::Right, Vec2::X),
+        (KeyCode::Up, Vec2::NEG_Y),
+        (KeyCode::Down, Vec2::Y),
+    ] {
+        if keys.pressed(key) {
+            rotation += dir * 1.5 * time.delta_seconds();
+        }
+    }
+    if rotation == Vec2::ZERO && scroll == 0. { return; }
+
+    for mut orbit in orbit_qry.iter_mut() {
+        orbit.yaw -= rotation.x;
+        orbit.pitch = (orbit.pitch + rotation.y).clamp(-1.4, 1.4);
+        orbit.radius = (orbit.radius - scroll * 0.5).clamp(3., 15.);
+    }
+}
+
+// Right mouse drags pan an infinite board, the wheel or a two finger pinch zooms
+fn handle_pan_input(
+    mut mouse_motion_evt_rdr: EventReader<MouseMotion>,
+    mut mouse_wheel_evt_rdr: EventReader<MouseWheel>,
+    mut view: ResMut<BoardView>,
+    mouse_btns: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    rules: Res<Rules>,
+) {
+    let motion: Vec2 = mouse_motion_evt_rdr.iter().map(|evt| evt.delta).sum();
+    let scroll: f32 = mouse_wheel_evt_rdr.iter().map(|evt| evt.y).sum();
+    if rules.variant != Variant::Infinite { return; }
+
+    let mut zoom = 1.1_f32.powf(scroll);
+    let fingers: Vec<_> = touches.iter().collect();
+    if let [a, b] = fingers[..] {
+        let prev_dist = a.previous_position().distance(b.previous_position());
+        if prev_dist > 0. {
+            zoom *= a.position().distance(b.position()) / prev_dist;
+        }
+    }
+
+    if mouse_btns.pressed(MouseButton::Right) && motion != Vec2::ZERO {
+        // Screen y grows downwards
+        view.offset += motion * Vec2::new(1., -1.);
+    }
+    if zoom != 1. {
+        view.zoom = (view.zoom * zoom).clamp(0.25, 4.);
+    }
+}
+
 fn handle_cell_picking(
-    mut commands: Commands,
     mut events: EventReader<PickingEvent>,
-    mut cell_picked_evt_wtr: EventWriter<CellPickedEvent>,
+    mut local_move_evt_wtr: EventWriter<LocalMoveEvent>,
     game_state: ResMut<State<GameState>>,
-    tex_atlas_handle: Res<TextureAtlasHandle>,
-    tex_atlas_indices: Res<TextureAtlasIndices>,
+    rules: Res<Rules>,
+    mark_choice: Res<MarkChoice>,
+    board: Res<Board>,
     cell_qry: Query<(&CellState, &CellPosition)>,
 ) {
+    // Cube cells are lit by update_cube_cells with their own materials
+    if rules.variant.is_cube() { return; }
+
     events.iter().for_each(|event| {
         let curr_state = &game_state.0;
         if *curr_state == GameState::GameOver { return; }
 
         match event {
             PickingEvent::Clicked(ent) => {
-                if let Ok((state, cell_pos)) = cell_qry.get(*ent) {
+                let Some((ent, _)) = target_cell(*ent, &rules, &board, &cell_qry) else { return; };
+                if let Ok((state, _)) = cell_qry.get(ent) {
                     if *state == CellState::None {
-                        let sprite_index = if *curr_state == GameState::XTurn {
-                            tex_atlas_indices.x
-                        } else {
-                            tex_atlas_indices.o
-                        };
-                        let new_state = if *curr_state == GameState::XTurn { CellState::X } else { CellState::O };
-                        
-                        let sprite_ent = commands.spawn(SpriteSheetBundle {
-                            texture_atlas: tex_atlas_handle.0.clone_weak(),
-                            sprite: TextureAtlasSprite::new(sprite_index),
-                            transform: Transform::from_scale(Vec3::splat(0.05)),
-                            ..default()
-                        }).id();
-
-                        commands.entity(*ent)
-                            .insert(new_state)
-                            .add_child(sprite_ent);
-
-                        cell_picked_evt_wtr.send(CellPickedEvent {
-                            entity: *ent,
+                        let player = curr_state.turn_mark();
+                        let new_state = if rules.wild { mark_choice.0 } else { player };
+
+                        local_move_evt_wtr.send(LocalMoveEvent {
+                            entity: ent,
                             state: new_state,
-                            position: *cell_pos,
                         });
                     }
                 }
@@ -91,25 +321,63 @@ fn handle_cell_picking(
     });
 }
 
+// Cell a click or hover on `ent` is about, in gravity mode the landing cell of its column
+fn target_cell(
+    ent: Entity,
+    rules: &Rules,
+    board: &Board,
+    cell_qry: &Query<(&CellState, &CellPosition)>,
+) -> Option<(Entity, CellPosition)> {
+    let (_, cell_pos) = cell_qry.get(ent).ok()?;
+    if rules.variant != Variant::Gravity {
+        return Some((ent, *cell_pos));
+    }
+
+    let state_at = |pos: CellPosition| {
+        board.0.get(&pos)
+            .and_then(|ent| cell_qry.get(*ent).ok())
+            .map_or(CellState::None, |(state, _)| *state)
+    };
+    let landing_pos = landing_cell(rules.variant, cell_pos.col, state_at)?;
+    board.0.get(&landing_pos).map(|ent| (*ent, landing_pos))
+}
+
 fn handle_cell_hover(
     mut commands: Commands,
     mut events: EventReader<PickingEvent>,
     mat_handles: Res<MaterialHandles>,
     game_state: ResMut<State<GameState>>,
-    cell_state_qry: Query<&CellState>,
+    rules: Res<Rules>,
+    board: Res<Board>,
+    cell_qry: Query<(&CellState, &CellPosition)>,
+    hinted_qry: Query<(), With<HintedCell>>,
 ) {
     events.iter().for_each(|event| {
         let curr_state = &game_state.0;
         if *curr_state == GameState::GameOver { return; }
+        // Leaving a hinted cell keeps the hint visible
+        let rest_material = |ent: Entity| if hinted_qry.contains(ent) {
+            mat_handles.hint.clone_weak()
+        } else {
+            mat_handles.transparent.clone_weak()
+        };
         match event {
             PickingEvent::Hover(HoverEvent::JustEntered(ent)) => {
-                if let Ok(_) = cell_state_qry.get(*ent) {
-                    commands.entity(*ent).insert(mat_handles.hovered.clone_weak());
+                if let Some((target, _)) = target_cell(*ent, &rules, &board, &cell_qry) {
+                    commands.entity(target).insert(mat_handles.hovered.clone_weak());
                 }
             },
             PickingEvent::Hover(HoverEvent::JustLeft(ent)) => {
-                if let Ok(_) = cell_state_qry.get(*ent) {
-                    commands.entity(*ent).insert(mat_handles.transparent.clone_weak());
+                let Ok((_, cell_pos)) = cell_qry.get(*ent) else { return; };
+                // The landing cell may have moved up since it was lit, clear the whole column
+                if rules.variant == Variant::Gravity {
+                    for row in rules.variant.rows() {
+                        if let Some(cell_ent) = board.0.get(&CellPosition { row, col: cell_pos.col, layer: 0 }) {
+                            commands.entity(*cell_ent).insert(rest_material(*cell_ent));
+                        }
+                    }
+                } else {
+                    commands.entity(*ent).insert(rest_material(*ent));
                 }
             },
             _ => (),
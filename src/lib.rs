@@ -0,0 +1,8 @@
+// The game's plugins, shared by the game itself and the headless tools in src/bin
+pub mod data;
+pub mod input;
+pub mod logic;
+pub mod ui;
+
+pub mod symmetry;
+pub mod tablebase;
@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+
+use crate::data::*;
+use super::save_profiles;
+
+// Lines where both the row and the column change from cell to cell
+fn is_diagonal(positions: &[CellPosition]) -> bool {
+    positions.windows(2).all(|pair| pair[0].row != pair[1].row && pair[0].col != pair[1].col)
+}
+
+// Achievements go to the local profiles seated at the sides, the computer and engines earn none.
+// Runs after rate_game so draw streaks can be read from the profiles' histories.
+pub fn unlock_achievements(
+    mut game_over_evt_rdr: EventReader<GameOverEvent>,
+    mut achievement_evt_wtr: EventWriter<AchievementUnlockedEvent>,
+    mut profiles: ResMut<Profiles>,
+    players: Res<Players>,
+    rules: Res<Rules>,
+    history: Res<MoveHistory>,
+) {
+    for evt in game_over_evt_rdr.iter() {
+        let mut unlocked_any = false;
+        for side in [CellState::X, CellState::O] {
+            if players.get(side).profile().is_some() { continue; }
+
+            let mut earned = Vec::new();
+            if evt.winner == side {
+                // In misère games the line belongs to the loser
+                let own_line = evt.winning_positions.as_ref().filter(|_| !rules.misere);
+                if own_line.map_or(false, |positions| is_diagonal(positions)) {
+                    earned.push(Achievement::DiagonalWin);
+                }
+                let own_moves = history.0.iter().filter(|(player, _)| *player == side).count();
+                if own_line.is_some() && own_moves <= 3 {
+                    earned.push(Achievement::QuickWin);
+                }
+                if players.get(side.opponent()).profile() == Some(computer_profile(Difficulty::Hard)) {
+                    earned.push(Achievement::BeatHardAi);
+                }
+            }
+
+            let name = profiles.seat(side).to_string();
+            let profile = profiles.get_or_create(&name);
+            let draw_streak = profile.history.len() >= DRAW_STREAK
+                && profile.history.iter().rev().take(DRAW_STREAK).all(|record| record.outcome == Outcome::Draw);
+            if draw_streak {
+                earned.push(Achievement::DrawStreak);
+            }
+
+            for achievement in earned {
+                if profile.achievements.contains(&achievement) { continue; }
+                info!("{} unlocked {:?}", name, achievement);
+                profile.achievements.push(achievement);
+                achievement_evt_wtr.send(AchievementUnlockedEvent {
+                    profile: name.clone(),
+                    achievement,
+                });
+                unlocked_any = true;
+            }
+        }
+        if unlocked_any {
+            save_profiles(&profiles);
+        }
+    }
+}
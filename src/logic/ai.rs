@@ -0,0 +1,549 @@
+use std::{sync::OnceLock, time::Duration};
+
+use bevy::{prelude::*, tasks::{AsyncComputeTaskPool, Task}};
+use futures_lite::future;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{data::*, tablebase::{self, Tablebase}};
+use super::{get_winning_positions, mcts_move, HumanController, SearchPosition};
+
+// Classic boards are small enough to search completely, cells are indexed row by row
+pub type Grid = [CellState; 9];
+
+pub fn grid_index(pos: CellPosition) -> usize {
+    ((pos.row + 1) * 3 + pos.col + 1) as usize
+}
+
+pub fn grid_position(index: usize) -> CellPosition {
+    CellPosition {
+        row: index as i32 / 3 - 1,
+        col: index as i32 % 3 - 1,
+        layer: 0,
+    }
+}
+
+// Lines are checked past the edges of the board, which are always empty
+fn grid_state(grid: &Grid, pos: CellPosition) -> CellState {
+    if (-1..=1).contains(&pos.row) && (-1..=1).contains(&pos.col) && pos.layer == 0 {
+        grid[grid_index(pos)]
+    } else {
+        CellState::None
+    }
+}
+
+// Classic positions are looked up in a table solved ahead of time by the tablebase binary,
+// wild positions aren't in it and are still searched
+static TABLEBASE: OnceLock<Tablebase> = OnceLock::new();
+
+fn tablebase() -> &'static Tablebase {
+    TABLEBASE.get_or_init(|| {
+        Tablebase::from_bytes(include_bytes!("../../assets/tablebase.bin"))
+            .expect("embedded tablebase is invalid")
+    })
+}
+
+fn table_mark(state: CellState) -> u8 {
+    match state {
+        CellState::None => tablebase::EMPTY,
+        CellState::X => tablebase::X,
+        CellState::O => tablebase::O,
+    }
+}
+
+// Marks a player may place, in wild tic-tac-toe either one
+fn playable_marks(player: CellState, rules: &Rules) -> &'static [CellState] {
+    if rules.wild {
+        &[CellState::X, CellState::O]
+    } else if player == CellState::X {
+        &[CellState::X]
+    } else {
+        &[CellState::O]
+    }
+}
+
+pub fn best_move(grid: &Grid, player: CellState, rules: &Rules) -> Option<(CellPosition, CellState)> {
+    if !rules.wild {
+        if let Some(entry) = tablebase().get(&grid.map(table_mark), table_mark(player), rules.misere) {
+            // The first best cell, like the search below would pick
+            let index = (0..9).find(|index| entry.best_moves & 1 << index != 0)?;
+            return Some((grid_position(index), player));
+        }
+    }
+
+    let mut grid = *grid;
+    let mut best = None;
+    let mut best_score = i32::MIN;
+    for index in 0..9 {
+        if grid[index] != CellState::None { continue; }
+        for mark in playable_marks(player, rules) {
+            let score = score_move(&mut grid, index, *mark, player, rules, 0, -i32::MAX, i32::MAX);
+            if score > best_score {
+                best_score = score;
+                best = Some((grid_position(index), *mark));
+            }
+        }
+    }
+    best
+}
+
+// Negamax score of `player` putting `mark` on `index`, positive when it is good for `player`.
+// Faster wins and slower losses score higher so the AI doesn't toy with its opponent.
+fn score_move(
+    grid: &mut Grid,
+    index: usize,
+    mark: CellState,
+    player: CellState,
+    rules: &Rules,
+    depth: i32,
+    alpha: i32,
+    beta: i32,
+) -> i32 {
+    let completes_line = get_winning_positions(
+        |pos| grid_state(grid, pos),
+        (mark, grid_position(index)),
+        rules.variant.line_length(),
+    ).is_some();
+    if completes_line {
+        return if rules.misere { depth - 10 } else { 10 - depth };
+    }
+
+    // The opponent replies with its best move, searched with the window seen from its side
+    grid[index] = mark;
+    let opponent = player.opponent();
+    let mut best_reply = None;
+    let (mut reply_alpha, reply_beta) = (-beta, -alpha);
+    'search: for next in 0..9 {
+        if grid[next] != CellState::None { continue; }
+        for reply_mark in playable_marks(opponent, rules) {
+            let score = score_move(grid, next, *reply_mark, opponent, rules, depth + 1, reply_alpha, reply_beta);
+            best_reply = Some(best_reply.map_or(score, |best: i32| best.max(score)));
+            reply_alpha = reply_alpha.max(score);
+            if reply_alpha >= reply_beta { break 'search; }
+        }
+    }
+    grid[index] = CellState::None;
+
+    // A full board without a line is a draw
+    best_reply.map_or(0, |best| -best)
+}
+
+// Solves every empty cell of a classic board for the side to move
+pub fn evaluate_moves(grid: &Grid, player: CellState, rules: &Rules) -> Vec<(CellPosition, Evaluation)> {
+    let mut grid = *grid;
+    let empty_cells = grid.iter().filter(|state| **state == CellState::None).count() as u32;
+    let table_scores = if rules.wild {
+        None
+    } else {
+        tablebase().move_scores(&grid.map(table_mark), table_mark(player), rules.misere)
+    };
+    let mut evaluations = Vec::new();
+    for index in 0..9 {
+        if grid[index] != CellState::None { continue; }
+        // In wild tic-tac-toe the better of the two marks counts
+        let score = match table_scores {
+            Some(scores) => scores[index].unwrap_or(0) as i32,
+            None => playable_marks(player, rules).iter()
+                .map(|mark| score_move(&mut grid, index, *mark, player, rules, 0, -i32::MAX, i32::MAX))
+                .max()
+                .unwrap_or(0),
+        };
+        // Lines score 10 minus the depth they were completed at
+        let evaluation = match score {
+            0 => Evaluation { outcome: Outcome::Draw, moves: empty_cells },
+            _ => Evaluation {
+                outcome: if score > 0 { Outcome::Win } else { Outcome::Loss },
+                moves: (11 - score.abs()) as u32,
+            },
+        };
+        evaluations.push((grid_position(index), evaluation));
+    }
+    evaluations
+}
+
+// With disappearing marks a game can go on forever and bigger boards are too big to search,
+// so only look one move ahead: complete a line when possible, otherwise take the cell the
+// opponent would complete one on. `state_at` is the board as seen by the side about to move.
+fn greedy_move(
+    legal: &[CellPosition],
+    state_at: impl Fn(CellState, CellPosition) -> CellState,
+    player: CellState,
+    rules: &Rules,
+) -> Option<(CellPosition, CellState)> {
+    if rules.misere {
+        return greedy_misere_move(legal, state_at, player, rules);
+    }
+
+    for side in [player, player.opponent()] {
+        for pos in legal.iter() {
+            for mark in playable_marks(side, rules) {
+                let completes_line = get_winning_positions(
+                    |pos| state_at(side, pos),
+                    (*mark, *pos),
+                    rules.variant.line_length(),
+                ).is_some();
+                if completes_line {
+                    let own_mark = if side == player { *mark } else { playable_marks(player, rules)[0] };
+                    return Some((*pos, own_mark));
+                }
+            }
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    let pos = legal.choose(&mut rng)?;
+    let mark = playable_marks(player, rules).choose(&mut rng)?;
+    Some((*pos, *mark))
+}
+
+// Misère version of greedy_move: never complete a line of one's own and leave the opponent
+// as few moves that don't complete one as possible, ideally none
+fn greedy_misere_move(
+    legal: &[CellPosition],
+    state_at: impl Fn(CellState, CellPosition) -> CellState,
+    player: CellState,
+    rules: &Rules,
+) -> Option<(CellPosition, CellState)> {
+    let line_length = rules.variant.line_length();
+    let opponent = player.opponent();
+    let mut rng = rand::thread_rng();
+
+    let safe_moves: Vec<_> = legal.iter()
+        .flat_map(|pos| playable_marks(player, rules).iter().map(move |mark| (*pos, *mark)))
+        .filter(|(pos, mark)| get_winning_positions(|pos| state_at(player, pos), (*mark, *pos), line_length).is_none())
+        .collect();
+    if safe_moves.is_empty() {
+        // Every move loses
+        let pos = legal.choose(&mut rng)?;
+        return Some((*pos, *playable_marks(player, rules).choose(&mut rng)?));
+    }
+
+    let safe_replies = |(pos, mark): (CellPosition, CellState)| {
+        let state_after = |cell_pos: CellPosition| if cell_pos == pos { mark } else { state_at(opponent, cell_pos) };
+        // Marks dropped into a column land on top of each other
+        let above = (rules.variant == Variant::Gravity && pos.row > *rules.variant.rows().start())
+            .then_some(CellPosition { row: pos.row - 1, ..pos });
+        legal.iter().copied()
+            .filter(|reply| *reply != pos)
+            .chain(above)
+            .flat_map(|reply| playable_marks(opponent, rules).iter().map(move |reply_mark| (reply, *reply_mark)))
+            .filter(|(reply, reply_mark)| {
+                get_winning_positions(state_after, (*reply_mark, *reply), line_length).is_none()
+            })
+            .count()
+    };
+    let scored: Vec<_> = safe_moves.iter().map(|mv| (*mv, safe_replies(*mv))).collect();
+    let fewest = scored.iter().map(|(_, replies)| *replies).min()?;
+    let best: Vec<_> = scored.into_iter().filter(|(_, replies)| *replies == fewest).collect();
+    best.choose(&mut rng).map(|(mv, _)| *mv)
+}
+
+// Lines in ultimate tic-tac-toe only count within a small board. Spreading the flat board out
+// with an empty row and column between the small boards keeps lines from running across two.
+fn spread_position(flat_pos: CellPosition) -> CellPosition {
+    CellPosition {
+        row: flat_pos.row + (flat_pos.row + 1).div_euclid(3),
+        col: flat_pos.col + (flat_pos.col + 1).div_euclid(3),
+        layer: flat_pos.layer,
+    }
+}
+
+// Cells and legal cells of an ultimate board spread out, legal cells stay in the same order
+fn spread_board(position: &Position) -> Position {
+    Position {
+        cells: position.cells.iter().map(|(pos, state)| (spread_position(*pos), *state)).collect(),
+        legal: position.legal.iter().map(|pos| spread_position(*pos)).collect(),
+        vanishing: default(),
+    }
+}
+
+// Why a suggested move is good, see explain_move
+pub fn explain_suggestion(
+    pos: CellPosition,
+    mark: CellState,
+    player: CellState,
+    rules: &Rules,
+    position: &Position,
+) -> HintReason {
+    if rules.variant == Variant::Ultimate {
+        let spread = spread_board(position);
+        return explain_move(spread_position(pos), mark, player, rules, |pos| spread.state_at(pos), &spread.legal);
+    }
+    explain_move(pos, mark, player, rules, |pos| position.state_at(pos), &position.legal)
+}
+
+// Why a suggested move is good, judged one move deep
+fn explain_move(
+    pos: CellPosition,
+    mark: CellState,
+    player: CellState,
+    rules: &Rules,
+    state_at: impl Fn(CellPosition) -> CellState,
+    legal: &[CellPosition],
+) -> HintReason {
+    // Lines lose in misère play, none of the labels below would make sense
+    if rules.misere { return HintReason::Best; }
+
+    let line_length = rules.variant.line_length();
+    let completes_line = |state_at: &dyn Fn(CellPosition) -> CellState, pos: CellPosition, mark: CellState| {
+        get_winning_positions(state_at, (mark, pos), line_length).is_some()
+    };
+
+    if completes_line(&state_at, pos, mark) {
+        return HintReason::Wins;
+    }
+    if playable_marks(player.opponent(), rules).iter().any(|mark| completes_line(&state_at, pos, *mark)) {
+        return HintReason::Blocks;
+    }
+
+    // A fork leaves two ways to complete a line, the opponent can only block one
+    let state_after = |cell_pos: CellPosition| if cell_pos == pos { mark } else { state_at(cell_pos) };
+    let threats = legal.iter()
+        .filter(|threat_pos| **threat_pos != pos)
+        .filter(|threat_pos| {
+            playable_marks(player, rules).iter().any(|mark| completes_line(&state_after, **threat_pos, *mark))
+        })
+        .count();
+    if threats >= 2 {
+        HintReason::Forks
+    } else {
+        HintReason::Best
+    }
+}
+
+// Move found looking only one move ahead, what the easier difficulties often play
+fn lookahead_move(player: CellState, rules: &Rules, position: &Position) -> Option<Move> {
+    match rules.variant {
+        Variant::Disappearing => {
+            // Each side sees its own oldest mark as already gone
+            let state_after_vanishing = |side: CellState, pos: CellPosition| {
+                match position.vanishing.get(&side) {
+                    Some(vanishing) if *vanishing == pos => CellState::None,
+                    _ => position.state_at(pos),
+                }
+            };
+            greedy_move(&position.legal, state_after_vanishing, player, rules)
+        },
+        // Too big to search completely, small boards are won or blocked like classic ones
+        Variant::Ultimate => {
+            let spread = spread_board(position);
+            let (pos, mark) = greedy_move(&spread.legal, |_, pos| spread.state_at(pos), player, rules)?;
+            let index = spread.legal.iter().position(|legal_pos| *legal_pos == pos)?;
+            Some((position.legal[index], mark))
+        },
+        _ => greedy_move(&position.legal, |_, pos| position.state_at(pos), player, rules),
+    }
+}
+
+// Move the computer would make for `player`, also used for hints
+pub fn suggest_move(player: CellState, rules: &Rules, position: &Position) -> Option<Move> {
+    match rules.variant {
+        // Solving a randomly turned board and turning the answer back varies which of
+        // several equally good moves gets played
+        Variant::Classic => {
+            let symmetry = *Symmetry::ALL.choose(&mut rand::thread_rng()).unwrap();
+            let mut grid = [CellState::None; 9];
+            for (pos, state) in position.cells.iter() {
+                grid[grid_index(pos.transformed(symmetry, rules.variant)?)] = *state;
+            }
+            let (pos, mark) = best_move(&grid, player, rules)?;
+            Some((pos.transformed(symmetry.inverse(), rules.variant)?, mark))
+        },
+        _ => lookahead_move(player, rules, position),
+    }
+}
+
+// Boards too big for minimax get a Monte Carlo tree search
+fn needs_tree_search(variant: Variant) -> bool {
+    matches!(variant, Variant::Gravity | Variant::Cube3 | Variant::Cube4 | Variant::Infinite)
+}
+
+pub struct AiController {
+    difficulty: Difficulty,
+    // Whether the current turn is played without searching
+    lookahead_only: bool,
+    think_timer: Timer,
+    // Tree search running off the main thread
+    search: Option<Task<Option<Move>>>,
+    mcts_settings: MctsSettings,
+}
+
+// Pause before the computer answers in game, so its moves don't appear in the same frame
+pub const AI_THINK_TIME: Duration = Duration::from_millis(400);
+
+impl AiController {
+    pub fn new(difficulty: Difficulty, mcts_settings: &MctsSettings, think_time: Duration) -> Self {
+        Self {
+            difficulty,
+            lookahead_only: false,
+            think_timer: Timer::new(think_time, TimerMode::Once),
+            search: None,
+            mcts_settings: difficulty.mcts_settings(mcts_settings),
+        }
+    }
+}
+
+impl Controller for AiController {
+    fn profile(&self) -> Option<String> {
+        Some(computer_profile(self.difficulty))
+    }
+
+    fn start_turn(&mut self) {
+        self.think_timer.reset();
+        self.lookahead_only = !rand::thread_rng().gen_bool(self.difficulty.search_chance());
+        // Dropping a task cancels it, a search of the previous position is no use anymore
+        self.search = None;
+    }
+
+    fn poll_move(&mut self, request: &MoveRequest) -> Option<Move> {
+        // Pretend to think for a moment
+        self.think_timer.tick(request.delta);
+
+        if self.lookahead_only {
+            if !self.think_timer.finished() { return None; }
+            return lookahead_move(request.player, &request.rules, request.position);
+        }
+        if !needs_tree_search(request.rules.variant) {
+            if !self.think_timer.finished() { return None; }
+            return suggest_move(request.player, &request.rules, request.position);
+        }
+
+        // The search starts right away and runs off the main thread so frames keep coming
+        if self.search.is_none() {
+            let position = SearchPosition {
+                cells: request.position.cells.clone(),
+                rules: request.rules,
+                player: request.player,
+            };
+            let settings = self.mcts_settings.clone();
+            self.search = Some(AsyncComputeTaskPool::get().spawn(async move {
+                mcts_move(position, &settings, &mut StdRng::from_entropy())
+            }));
+        }
+        if !self.think_timer.finished() { return None; }
+
+        let result = future::block_on(future::poll_once(self.search.as_mut()?))?;
+        self.search = None;
+        result
+    }
+}
+
+fn is_computer(players: &Players, side: CellState) -> bool {
+    players.get(side).profile().map_or(false, |name| name.starts_with(COMPUTER_PROFILE))
+}
+
+// Lets the computer take over O, or hands every side back to the local player
+pub fn toggle_ai(
+    mut toggle_ai_evt_rdr: EventReader<ToggleAiEvent>,
+    mut players: ResMut<Players>,
+    mcts_settings: Res<MctsSettings>,
+    difficulty: Res<Difficulty>,
+) {
+    for _ in toggle_ai_evt_rdr.iter() {
+        if players.is_human(CellState::X) && players.is_human(CellState::O) {
+            players.o = Box::new(AiController::new(*difficulty, &mcts_settings, AI_THINK_TIME));
+        } else {
+            players.x = Box::new(HumanController);
+            players.o = Box::new(HumanController);
+        }
+    }
+}
+
+// Picks the next difficulty, a game against the computer starts over at the new one
+pub fn next_difficulty(
+    mut next_difficulty_evt_rdr: EventReader<NextDifficultyEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut difficulty: ResMut<Difficulty>,
+    mut players: ResMut<Players>,
+    mcts_settings: Res<MctsSettings>,
+) {
+    for _ in next_difficulty_evt_rdr.iter() {
+        *difficulty = difficulty.next();
+        info!("computer difficulty {}", difficulty.label());
+
+        let mut restart = false;
+        for side in [CellState::X, CellState::O] {
+            if !is_computer(&players, side) { continue; }
+            let controller = Box::new(AiController::new(*difficulty, &mcts_settings, AI_THINK_TIME));
+            if side == CellState::O { players.o = controller; } else { players.x = controller; }
+            restart = true;
+        }
+        if restart {
+            new_game_evt_wtr.send(NewGameEvent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use super::*;
+
+    fn board(marks: &[((i32, i32), CellState)]) -> HashMap<CellPosition, CellState> {
+        marks.iter().map(|((row, col), state)| (cell(*row, *col), *state)).collect()
+    }
+
+    fn cell(row: i32, col: i32) -> CellPosition {
+        CellPosition { row, col, layer: 0 }
+    }
+
+    fn greedy(cells: &HashMap<CellPosition, CellState>, legal: &[CellPosition], misere: bool) -> Option<Move> {
+        let rules = Rules { variant: Variant::Disappearing, misere, wild: false };
+        let state_at = |_, pos| cells.get(&pos).copied().unwrap_or(CellState::None);
+        greedy_move(legal, state_at, CellState::X, &rules)
+    }
+
+    // X X .
+    // O O X
+    // X O .
+    #[test]
+    fn greedy_completes_lines_only_in_normal_play() {
+        use CellState::{O, X};
+        let cells = board(&[
+            ((-1, -1), X), ((-1, 0), X),
+            ((0, -1), O), ((0, 0), O), ((0, 1), X),
+            ((1, -1), X), ((1, 0), O),
+        ]);
+        let legal = [cell(-1, 1), cell(1, 1)];
+        for _ in 0..20 {
+            assert_eq!(greedy(&cells, &legal, false), Some((cell(-1, 1), X)));
+            assert_eq!(greedy(&cells, &legal, true), Some((cell(1, 1), X)));
+        }
+    }
+
+    // . . X
+    // X O X
+    // O X O
+    #[test]
+    fn greedy_misere_forces_the_opponent_into_a_line() {
+        use CellState::{O, X};
+        let cells = board(&[
+            ((-1, 1), X),
+            ((0, -1), X), ((0, 0), O), ((0, 1), X),
+            ((1, -1), O), ((1, 0), X), ((1, 1), O),
+        ]);
+        let legal = [cell(-1, -1), cell(-1, 0)];
+        for _ in 0..20 {
+            // Normal play blocks the diagonal, misère play leaves it for O to complete
+            assert_eq!(greedy(&cells, &legal, false), Some((cell(-1, -1), X)));
+            assert_eq!(greedy(&cells, &legal, true), Some((cell(-1, 0), X)));
+        }
+    }
+
+    #[test]
+    fn ultimate_lines_stay_within_a_small_board() {
+        use CellState::X;
+        // X X at the top of the middle small board, and two more in the board to its right
+        // that would make a line with the middle cell's right neighbour if boards joined up
+        let cells = board(&[((-1, -1), X), ((-1, 0), X), ((0, 2), X), ((0, 3), X)]);
+        let legal: Vec<_> = [(0, 1), (-1, 1), (0, -1), (0, 0), (1, -1), (1, 0), (1, 1)]
+            .iter()
+            .map(|(row, col)| cell(*row, *col))
+            .collect();
+        let position = Position { cells, legal, vanishing: default() };
+        let rules = Rules { variant: Variant::Ultimate, misere: false, wild: false };
+        assert_eq!(suggest_move(X, &rules, &position), Some((cell(-1, 1), X)));
+        assert_eq!(explain_suggestion(cell(-1, 1), X, X, &rules, &position), HintReason::Wins);
+        assert_eq!(explain_suggestion(cell(0, 1), X, X, &rules, &position), HintReason::Best);
+    }
+}
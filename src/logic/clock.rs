@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::data::*;
+use super::is_legal_cell;
+
+pub fn change_clock_mode(
+    mut next_clock_mode_evt_rdr: EventReader<NextClockModeEvent>,
+    mut settings: ResMut<ClockSettings>,
+) {
+    for _ in next_clock_mode_evt_rdr.iter() {
+        let presets = ClockSettings::PRESETS;
+        let curr = presets.iter()
+            .position(|preset| preset.mode == settings.mode)
+            .unwrap_or(0);
+        *settings = presets[(curr + 1) % presets.len()];
+    }
+}
+
+pub fn reset_turn_clock(
+    mut clock: ResMut<TurnClock>,
+    mut new_game_evt_rdr: EventReader<NewGameEvent>,
+    settings: Res<ClockSettings>,
+) {
+    let new_game = new_game_evt_rdr.iter().count() > 0;
+    if !settings.is_changed() && !new_game { return; }
+    match settings.mode {
+        ClockMode::Off => (),
+        ClockMode::PerMove(length) | ClockMode::Game(length) => clock.reset(length),
+    }
+}
+
+pub fn start_move_clock(
+    mut clock: ResMut<TurnClock>,
+    settings: Res<ClockSettings>,
+    game_state: Res<State<GameState>>,
+) {
+    let ClockMode::PerMove(length) = settings.mode else { return; };
+    if let Some(timer) = clock.get_mut(game_state.0.turn_mark()) {
+        *timer = Timer::new(length, TimerMode::Once);
+    }
+}
+
+pub fn tick_turn_clock(
+    mut clock: ResMut<TurnClock>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut game_over_evt_wtr: EventWriter<GameOverEvent>,
+    mut cell_picked_evt_wtr: EventWriter<CellPickedEvent>,
+    settings: Res<ClockSettings>,
+    rules: Res<Rules>,
+    ultimate: Res<UltimateBoard>,
+    game_state: Res<State<GameState>>,
+    board: Res<Board>,
+    cell_qry: Query<(Entity, &CellState, &CellPosition, Option<&SubBoard>)>,
+    time: Res<Time>,
+) {
+    if settings.mode == ClockMode::Off { return; }
+
+    let player = game_state.0.turn_mark();
+    let Some(timer) = clock.get_mut(player) else { return; };
+    timer.tick(time.delta());
+    // A player out of game time keeps timing out on every later move
+    if !timer.finished() { return; }
+
+    match settings.on_timeout {
+        TimeoutRule::RandomMove => {
+            let state_at = |pos: CellPosition| {
+                board.0.get(&pos)
+                    .and_then(|ent| cell_qry.get(*ent).ok())
+                    .map_or(CellState::None, |(_, state, ..)| *state)
+            };
+            let legal_cells: Vec<_> = cell_qry.iter()
+                .filter(|(_, state, pos, sub_board)| {
+                    is_legal_cell(&rules, &ultimate, **state, *sub_board, **pos, state_at)
+                })
+                .collect();
+            let mut rng = rand::thread_rng();
+            if let Some((ent, _, pos, _)) = legal_cells.choose(&mut rng) {
+                let mark = if rules.wild {
+                    *[CellState::X, CellState::O].choose(&mut rng).unwrap()
+                } else {
+                    player
+                };
+                cell_picked_evt_wtr.send(CellPickedEvent {
+                    entity: *ent,
+                    state: mark,
+                    player,
+                    position: **pos,
+                });
+            }
+        },
+        TimeoutRule::Lose => {
+            next_game_state.set(GameState::GameOver);
+            game_over_evt_wtr.send(GameOverEvent {
+                winner: player.opponent(),
+                winning_positions: None,
+            });
+        },
+    }
+}
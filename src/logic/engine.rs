@@ -0,0 +1,370 @@
+// External engines are separate programs that play one side over stdin and stdout, one command
+// per line. Cells are written row,col,layer in the board's centered coordinates, ultimate
+// boards are flattened onto one 9x9 grid.
+//
+//   tictactoe                                  sent once after starting the engine
+//   newgame <rows> <cols> <layers> <k> <variant> [misere] [wild]
+//                                              rows and cols are 0 on infinite boards
+//   position <x|o> [<row>,<col>,<layer>:<X|O> ...]
+//                                              side to move and every mark on the board
+//   legal [<row>,<col>,<layer> ...]            cells the side to move may pick
+//   go <id> <milliseconds>                     search number and time the engine has to answer
+//   quit
+//
+// The engine answers `go` with `bestmove <id> <row>,<col>,<layer> [X|O]`, repeating the id of
+// the search it answers, the mark is only needed in wild tic-tac-toe. Answers to searches that
+// were given up on are skipped. Other lines are logged, `id name <name>` is a good first one.
+//
+// Engine commands are split into words like a shell would, quotes keep spaces in a word.
+// Engines that answer too late, with an illegal move or not at all get a random move played
+// for them instead, like players running out of time.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{mpsc::{self, Receiver, TryRecvError}, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::data::*;
+
+// Engines get this much longer than they were told before their move is given up on
+const ENGINE_GRACE: Duration = Duration::from_millis(500);
+// How long an engine has to exit after being told to quit
+const ENGINE_QUIT_TIME: Duration = Duration::from_millis(100);
+
+pub struct EngineController {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Mutex<Receiver<String>>,
+    move_time: Duration,
+    // Whether the engine still has to hear about the game being played
+    needs_new_game: bool,
+    // Time since the engine was told to go
+    thinking: Option<Duration>,
+    // Number of the last search, only answers repeating it are played
+    search_id: u32,
+    // The engine quit or can't be written to, moves are random from then on
+    dead: bool,
+}
+
+impl EngineController {
+    pub fn spawn(command: &str, move_time: Duration) -> std::io::Result<Self> {
+        let words = split_command(command).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "unmatched quote")
+        })?;
+        let Some((program, args)) = words.split_first() else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty command"));
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // Reading blocks, so lines are passed over from a thread of their own
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break; };
+                if sender.send(line).is_err() { break; }
+            }
+        });
+
+        let mut engine = Self {
+            command: command.to_string(),
+            child,
+            stdin,
+            lines: Mutex::new(receiver),
+            move_time,
+            needs_new_game: true,
+            thinking: None,
+            search_id: 0,
+            dead: false,
+        };
+        engine.send("tictactoe");
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) {
+        if self.dead { return; }
+        if writeln!(self.stdin, "{}", line).and_then(|_| self.stdin.flush()).is_err() {
+            warn!("engine `{}` stopped listening", self.command);
+            self.dead = true;
+        }
+    }
+
+    fn start_search(&mut self, request: &MoveRequest) {
+        let rules = request.rules;
+        if self.needs_new_game {
+            let (rows, cols) = match rules.variant {
+                Variant::Infinite => (0, 0),
+                Variant::Ultimate => (9, 9),
+                _ => rules.variant.size(),
+            };
+            let layers = rules.variant.layers().count();
+            let mut line = format!(
+                "newgame {} {} {} {} {}",
+                rows, cols, layers, rules.variant.line_length(), variant_name(rules.variant),
+            );
+            if rules.misere { line.push_str(" misere"); }
+            if rules.wild { line.push_str(" wild"); }
+            self.send(&line);
+            self.needs_new_game = false;
+        }
+
+        let mut marks: Vec<_> = request.position.cells.iter()
+            .filter(|(_, state)| **state != CellState::None)
+            .collect();
+        marks.sort_by_key(|(pos, _)| (pos.layer, pos.row, pos.col));
+        let mut line = format!("position {}", mark_name(request.player).to_lowercase());
+        for (pos, state) in marks {
+            line.push_str(&format!(" {}:{}", cell_name(*pos), mark_name(*state)));
+        }
+        self.send(&line);
+
+        let mut line = "legal".to_string();
+        for pos in request.position.legal.iter() {
+            line.push_str(&format!(" {}", cell_name(*pos)));
+        }
+        self.send(&line);
+
+        self.search_id += 1;
+        self.send(&format!("go {} {}", self.search_id, self.move_time.as_millis()));
+        self.thinking = Some(Duration::ZERO);
+    }
+
+    // The engine's answer to the current search if it has given one, skipping anything else
+    // it says and answers to searches that were given up on
+    fn read_best_move(&mut self) -> Option<String> {
+        loop {
+            let received = self.lines.lock().unwrap().try_recv();
+            match received {
+                Ok(line) => match line.strip_prefix("bestmove ") {
+                    Some(answer) => if let Some(best_move) = answer_to(answer, self.search_id) {
+                        return Some(best_move);
+                    },
+                    None => info!("engine `{}`: {}", self.command, line),
+                },
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    warn!("engine `{}` quit", self.command);
+                    self.dead = true;
+                    return None;
+                },
+            }
+        }
+    }
+}
+
+impl Controller for EngineController {
+    fn profile(&self) -> Option<String> {
+        Some(engine_profile(&self.command))
+    }
+
+    fn new_game(&mut self) {
+        self.needs_new_game = true;
+        // Only the side to move starts a turn, an engine thinking when the game was reset
+        // has to drop its answer here
+        self.thinking = None;
+    }
+
+    fn start_turn(&mut self) {
+        self.thinking = None;
+    }
+
+    fn poll_move(&mut self, request: &MoveRequest) -> Option<Move> {
+        if self.dead { return random_move(request); }
+
+        let Some(thinking) = self.thinking.as_mut() else {
+            self.start_search(request);
+            return None;
+        };
+        *thinking += request.delta;
+        let timed_out = *thinking > self.move_time + ENGINE_GRACE;
+
+        if let Some(best_move) = self.read_best_move() {
+            self.thinking = None;
+            match parse_move(&best_move, request) {
+                Some(mv) => return Some(mv),
+                None => {
+                    warn!("engine `{}` played an illegal move: {}", self.command, best_move);
+                    return random_move(request);
+                },
+            }
+        }
+        if timed_out {
+            warn!("engine `{}` ran out of time", self.command);
+            self.thinking = None;
+            return random_move(request);
+        }
+        if self.dead { return random_move(request); }
+        None
+    }
+}
+
+impl Drop for EngineController {
+    fn drop(&mut self) {
+        self.send("quit");
+        // The engine gets a moment to exit on its own, one that ignores quit is stopped
+        let started = Instant::now();
+        while started.elapsed() < ENGINE_QUIT_TIME {
+            if !matches!(self.child.try_wait(), Ok(None)) { return; }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn variant_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Classic => "classic",
+        Variant::Ultimate => "ultimate",
+        Variant::Disappearing => "disappearing",
+        Variant::Gravity => "gravity",
+        Variant::Cube3 => "cube3",
+        Variant::Cube4 => "cube4",
+        Variant::Infinite => "infinite",
+    }
+}
+
+fn mark_name(state: CellState) -> &'static str {
+    match state {
+        CellState::X => "X",
+        CellState::O => "O",
+        CellState::None => "-",
+    }
+}
+
+fn cell_name(pos: CellPosition) -> String {
+    format!("{},{},{}", pos.row, pos.col, pos.layer)
+}
+
+// Words of an engine command, split like a shell would. Single quotes keep everything inside
+// them, double quotes and backslashes keep spaces. None if a quote is left open.
+fn split_command(command: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // Quotes start a word even when there is nothing between them
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            },
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => word.push(chars.next()?),
+                        c => word.push(c),
+                    }
+                }
+            },
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+// The move in a `bestmove` answer if it answers search `search_id`
+fn answer_to(answer: &str, search_id: u32) -> Option<String> {
+    let (id, best_move) = answer.trim().split_once(' ')?;
+    (id.parse() == Ok(search_id)).then(|| best_move.trim().to_string())
+}
+
+fn parse_move(best_move: &str, request: &MoveRequest) -> Option<Move> {
+    let mut parts = best_move.split_whitespace();
+    let coords: Vec<i32> = parts.next()?
+        .split(',')
+        .map(|coord| coord.parse().ok())
+        .collect::<Option<_>>()?;
+    let [row, col, layer] = coords[..] else { return None; };
+    let pos = CellPosition { row, col, layer };
+
+    let mark = match parts.next() {
+        Some("X" | "x") => CellState::X,
+        Some("O" | "o") => CellState::O,
+        Some(_) => return None,
+        None => request.player,
+    };
+    let own_mark = request.rules.wild || mark == request.player;
+    (own_mark && request.position.legal.contains(&pos)).then_some((pos, mark))
+}
+
+fn random_move(request: &MoveRequest) -> Option<Move> {
+    let mut rng = rand::thread_rng();
+    let pos = request.position.legal.choose(&mut rng)?;
+    let mark = if request.rules.wild {
+        *[CellState::X, CellState::O].choose(&mut rng).unwrap()
+    } else {
+        request.player
+    };
+    Some((*pos, mark))
+}
+
+// `--x-engine <command>` and `--o-engine <command>` hand a side to an external engine,
+// `--engine-time <milliseconds>` sets how long engines get per move
+pub fn load_engines(mut players: ResMut<Players>) {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |name: &str| {
+        args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1))
+    };
+    let move_time = arg("--engine-time")
+        .and_then(|millis| millis.parse().ok())
+        .map_or(Duration::from_secs(1), Duration::from_millis);
+
+    for (side, flag) in [(CellState::X, "--x-engine"), (CellState::O, "--o-engine")] {
+        let Some(command) = arg(flag) else { continue; };
+        match EngineController::spawn(command, move_time) {
+            Ok(engine) => match side {
+                CellState::X => players.x = Box::new(engine),
+                _ => players.o = Box::new(engine),
+            },
+            Err(err) => error!("failed to start engine `{}`: {}", command, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_split_like_a_shell() {
+        assert_eq!(split_command("engine --depth 3"), Some(vec!["engine".into(), "--depth".into(), "3".into()]));
+        assert_eq!(
+            split_command("'/opt/my engines/bot' \"a b\" c\\ d"),
+            Some(vec!["/opt/my engines/bot".into(), "a b".into(), "c d".into()]),
+        );
+        assert_eq!(split_command("bot ''"), Some(vec!["bot".into(), "".into()]));
+        assert_eq!(split_command("  "), Some(vec![]));
+        assert_eq!(split_command("bot 'open"), None);
+    }
+
+    #[test]
+    fn only_answers_to_the_current_search_count() {
+        assert_eq!(answer_to("3 0,1,0", 3), Some("0,1,0".to_string()));
+        assert_eq!(answer_to("3 0,1,0 X", 3), Some("0,1,0 X".to_string()));
+        assert_eq!(answer_to("2 0,1,0", 3), None);
+        assert_eq!(answer_to("0,1,0", 3), None);
+    }
+}
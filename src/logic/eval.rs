@@ -0,0 +1,46 @@
+use bevy::prelude::*;
+
+use crate::data::*;
+use super::{evaluate_moves, grid_index};
+
+pub fn toggle_eval_overlay(
+    mut toggle_eval_overlay_evt_rdr: EventReader<ToggleEvalOverlayEvent>,
+    mut overlay: ResMut<EvalOverlay>,
+) {
+    for _ in toggle_eval_overlay_evt_rdr.iter() {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+// Moves are only applied at the end of the frame they were picked in,
+// so the position is solved on the first frame without a new CellPickedEvent
+pub fn evaluate_position(
+    mut cell_picked_evt_rdr: EventReader<CellPickedEvent>,
+    mut new_game_evt_rdr: EventReader<NewGameEvent>,
+    mut overlay: ResMut<EvalOverlay>,
+    mut pending: Local<bool>,
+    game_state: Res<State<GameState>>,
+    rules: Res<Rules>,
+    board: Res<Board>,
+    cell_qry: Query<&CellState>,
+) {
+    let moved = cell_picked_evt_rdr.iter().count() > 0;
+    let restarted = new_game_evt_rdr.iter().count() > 0;
+    if moved || restarted || overlay.is_changed() && overlay.enabled {
+        *pending = true;
+    }
+    if moved || restarted || !*pending { return; }
+    *pending = false;
+
+    overlay.evaluations.clear();
+    let player = game_state.0.turn_mark();
+    if !overlay.enabled || !rules.variant.can_be_solved() || player == CellState::None { return; }
+
+    let mut grid = [CellState::None; 9];
+    for (pos, ent) in board.0.iter() {
+        if let Ok(state) = cell_qry.get(*ent) {
+            grid[grid_index(*pos)] = *state;
+        }
+    }
+    overlay.evaluations.extend(evaluate_moves(&grid, player, &rules));
+}
@@ -0,0 +1,222 @@
+use bevy::utils::HashMap;
+
+use crate::data::*;
+use super::{apply_move, is_legal_cell, line_winner, MoveResult};
+
+// A game kept outside the world, for playing games nobody watches. Moves go through the same
+// legality checks and apply_move as update_game_state. Positions are flat, see flat_position.
+pub struct Game {
+    pub rules: Rules,
+    pub to_move: CellState,
+    pub moves: Vec<Move>,
+    // Winner once the game is over, CellState::None for a draw
+    pub result: Option<CellState>,
+    cells: HashMap<CellPosition, CellState>,
+    // Flat position and side of every mark still on the board, oldest first
+    placements: Vec<(CellPosition, CellState)>,
+    // Only the owners and the forced small board are used
+    ultimate: UltimateBoard,
+}
+
+impl Game {
+    pub fn new(rules: Rules, starter: CellState) -> Self {
+        // Ultimate boards are three small boards wide
+        let (rows, cols) = match rules.variant {
+            Variant::Ultimate => (-4..=4, -4..=4),
+            _ => (rules.variant.rows(), rules.variant.cols()),
+        };
+        let mut cells = HashMap::new();
+        for layer in rules.variant.layers() {
+            for row in rows.clone() {
+                for col in cols.clone() {
+                    cells.insert(CellPosition { row, col, layer }, CellState::None);
+                }
+            }
+        }
+
+        let mut ultimate = UltimateBoard::default();
+        if rules.variant == Variant::Ultimate {
+            for row in -1..=1 {
+                for col in -1..=1 {
+                    ultimate.boards.insert(CellPosition { row, col, layer: 0 }, Board::default());
+                }
+            }
+        }
+
+        Self {
+            rules,
+            to_move: starter,
+            moves: Vec::new(),
+            result: None,
+            cells,
+            placements: Vec::new(),
+            ultimate,
+        }
+    }
+
+    pub fn state_at(&self, pos: CellPosition) -> CellState {
+        self.cells.get(&pos).copied().unwrap_or(CellState::None)
+    }
+
+    fn is_legal(&self, flat_pos: CellPosition) -> bool {
+        let Some(state) = self.cells.get(&flat_pos) else { return false; };
+        let (sub_board, pos) = match self.rules.variant {
+            Variant::Ultimate => {
+                let (sub_board, pos) = split_position(flat_pos);
+                (Some(SubBoard(sub_board)), pos)
+            },
+            _ => (None, flat_pos),
+        };
+        is_legal_cell(&self.rules, &self.ultimate, *state, sub_board.as_ref(), pos, |pos| self.state_at(pos))
+    }
+
+    // Mark `side` loses by placing another one, with disappearing marks
+    fn vanishing(&self, side: CellState) -> Option<CellPosition> {
+        if self.rules.variant != Variant::Disappearing { return None; }
+        let own: Vec<_> = self.placements.iter().filter(|(_, player)| *player == side).collect();
+        if own.len() < MAX_MARKS { return None; }
+        own.first().map(|(pos, _)| *pos)
+    }
+
+    // What controllers are shown when asked for a move
+    pub fn position(&self) -> Position {
+        let mut legal: Vec<_> = self.cells.keys().copied().filter(|pos| self.is_legal(*pos)).collect();
+        legal.sort_by_key(|pos| (pos.layer, pos.row, pos.col));
+        let vanishing = [CellState::X, CellState::O].into_iter()
+            .filter_map(|side| self.vanishing(side).map(|pos| (side, pos)))
+            .collect();
+        Position {
+            cells: self.cells.clone(),
+            legal,
+            vanishing,
+        }
+    }
+
+    pub fn play(&mut self, (flat_pos, mark): Move) -> Result<(), String> {
+        if self.result.is_some() {
+            return Err("the game is over".to_string());
+        }
+        if !self.is_legal(flat_pos) {
+            return Err(format!("{:?} can't be played", flat_pos));
+        }
+        if !self.rules.wild && mark != self.to_move {
+            return Err(format!("{:?} can't place {:?}", self.to_move, mark));
+        }
+
+        let player = self.to_move;
+        let vanishing = self.vanishing(player);
+        let outcome = apply_move(
+            &self.rules,
+            &self.ultimate,
+            self.cells.keys().copied(),
+            |pos| self.state_at(pos),
+            vanishing,
+            player,
+            (flat_pos, mark),
+        );
+
+        if let Some(vanishing) = vanishing {
+            self.cells.insert(vanishing, CellState::None);
+            self.placements.retain(|(pos, _)| *pos != vanishing);
+        }
+        if let Some((sub_board, owner)) = outcome.claimed {
+            self.ultimate.owners.insert(sub_board, owner);
+        }
+        self.ultimate.forced = outcome.forced;
+        self.cells.insert(flat_pos, mark);
+        for pos in outcome.grown {
+            self.cells.insert(pos, CellState::None);
+        }
+        self.placements.push((flat_pos, player));
+        self.moves.push((flat_pos, mark));
+        self.to_move = player.opponent();
+
+        self.result = match outcome.result {
+            MoveResult::Won(_) => Some(line_winner(&self.rules, player)),
+            MoveResult::Draw => Some(CellState::None),
+            MoveResult::Continue => None,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(row: i32, col: i32) -> CellPosition {
+        CellPosition { row, col, layer: 0 }
+    }
+
+    fn rules(variant: Variant, misere: bool) -> Rules {
+        Rules { variant, misere, wild: false }
+    }
+
+    fn play_all(game: &mut Game, cells: &[(i32, i32)]) {
+        for (row, col) in cells {
+            let mark = game.to_move;
+            game.play((cell(*row, *col), mark)).unwrap();
+        }
+    }
+
+    #[test]
+    fn lines_end_the_game() {
+        let moves = [(0, 0), (-1, -1), (0, 1), (-1, 0), (0, -1)];
+        let mut game = Game::new(rules(Variant::Classic, false), CellState::X);
+        play_all(&mut game, &moves);
+        assert_eq!(game.result, Some(CellState::X));
+        assert!(game.play((cell(1, 1), CellState::O)).is_err());
+
+        let mut game = Game::new(rules(Variant::Classic, true), CellState::X);
+        play_all(&mut game, &moves);
+        assert_eq!(game.result, Some(CellState::O));
+    }
+
+    #[test]
+    fn full_board_is_a_draw() {
+        let mut game = Game::new(rules(Variant::Classic, false), CellState::O);
+        play_all(&mut game, &[(0, 0), (-1, -1), (1, 1), (-1, 1), (-1, 0), (1, 0), (0, -1), (0, 1), (1, -1)]);
+        assert_eq!(game.result, Some(CellState::None));
+        assert_eq!(game.moves.len(), 9);
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let mut game = Game::new(rules(Variant::Classic, false), CellState::X);
+        assert!(game.play((cell(0, 0), CellState::O)).is_err());
+        assert!(game.play((cell(2, 0), CellState::X)).is_err());
+        game.play((cell(0, 0), CellState::X)).unwrap();
+        assert!(game.play((cell(0, 0), CellState::O)).is_err());
+        assert_eq!(game.to_move, CellState::O);
+    }
+
+    #[test]
+    fn gravity_marks_must_land() {
+        let mut game = Game::new(rules(Variant::Gravity, false), CellState::X);
+        let bottom = *Variant::Gravity.rows().end();
+        assert!(game.play((cell(bottom - 1, 0), CellState::X)).is_err());
+        game.play((cell(bottom, 0), CellState::X)).unwrap();
+        game.play((cell(bottom - 1, 0), CellState::O)).unwrap();
+    }
+
+    #[test]
+    fn ultimate_sends_the_opponent_to_a_small_board() {
+        let mut game = Game::new(rules(Variant::Ultimate, false), CellState::X);
+        // Top left cell of the middle small board sends O to the top left small board
+        game.play((cell(-1, -1), CellState::X)).unwrap();
+        let legal = game.position().legal;
+        assert_eq!(legal.len(), 9);
+        assert!(legal.iter().all(|pos| (-4..=-2).contains(&pos.row) && (-4..=-2).contains(&pos.col)));
+        assert!(game.play((cell(0, 0), CellState::O)).is_err());
+    }
+
+    #[test]
+    fn disappearing_marks_vanish() {
+        let mut game = Game::new(rules(Variant::Disappearing, false), CellState::X);
+        play_all(&mut game, &[(-1, -1), (1, 1), (-1, 1), (1, -1), (1, 0)]);
+        assert_eq!(game.position().vanishing.get(&CellState::X), Some(&cell(-1, -1)));
+        play_all(&mut game, &[(0, 1), (0, -1)]);
+        assert_eq!(game.state_at(cell(-1, -1)), CellState::None);
+        assert_eq!(game.result, None);
+    }
+}
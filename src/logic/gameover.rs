@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 
 use crate::{
     data::MaterialHandles,
@@ -10,14 +10,16 @@ use crate::{
 pub fn highlight_winning_cells(
     mut commands: Commands,
     mut game_over_evt_rdr: EventReader<GameOverEvent>,
-    cell_qry: Query<(Entity, &CellState, &CellPosition)>,
     mat_handles: Res<MaterialHandles>,
+    board: Res<Board>,
+    cube_cell_qry: Query<(), With<CubeCell>>,
 ) {
     for evt in game_over_evt_rdr.iter() {
-        let winning_positions = evt.winning_positions;
-        for (ent, state, pos) in cell_qry.iter() {
-            if winning_positions.contains(pos) {
-                commands.entity(ent)
+        let Some(winning_positions) = &evt.winning_positions else { continue; };
+        for pos in winning_positions.iter() {
+            // Winning cube cells are lit by update_cube_cells
+            if let Some(ent) = board.0.get(pos).filter(|ent| !cube_cell_qry.contains(**ent)) {
+                commands.entity(*ent)
                     .insert(mat_handles.winner.clone_weak())
                     .insert(BlinkingTimer::new(
                         Duration::from_millis(500),
@@ -28,179 +30,447 @@ pub fn highlight_winning_cells(
     }
 }
 
+pub fn is_legal_cell(
+    rules: &Rules,
+    ultimate: &UltimateBoard,
+    state: CellState,
+    sub_board: Option<&SubBoard>,
+    pos: CellPosition,
+    state_at: impl Fn(CellPosition) -> CellState,
+) -> bool {
+    state == CellState::None && match rules.variant {
+        Variant::Classic | Variant::Disappearing | Variant::Cube3 | Variant::Cube4 | Variant::Infinite => true,
+        Variant::Ultimate => sub_board.map_or(false, |sub_board| ultimate.is_playable(sub_board.0)),
+        Variant::Gravity => landing_cell(rules.variant, pos.col, state_at) == Some(pos),
+    }
+}
+
+// The line belongs to whoever completed it, in misère play that player loses
+pub fn line_winner(rules: &Rules, player: CellState) -> CellState {
+    if rules.misere { player.opponent() } else { player }
+}
+
+pub enum MoveResult {
+    Continue,
+    Won(Vec<CellPosition>),
+    Draw,
+}
+
+// Everything a move changes besides its own cell, worked out the same way for the board on
+// screen and for Game. Positions are flat, see flat_position.
+pub struct MoveOutcome {
+    pub result: MoveResult,
+    // Small board the move won, or drew with CellState::None
+    pub claimed: Option<(CellPosition, CellState)>,
+    // Small board the opponent has to play in next
+    pub forced: Option<CellPosition>,
+    // Cells an infinite board grows by
+    pub grown: Vec<CellPosition>,
+}
+
+// `player` puts `mark` on `flat_pos`. `state_at` is the board before the move, `cells` every
+// cell on it and `vanishing` the mark that disappears first, with disappearing marks.
+pub fn apply_move(
+    rules: &Rules,
+    ultimate: &UltimateBoard,
+    cells: impl IntoIterator<Item = CellPosition>,
+    state_at: impl Fn(CellPosition) -> CellState,
+    vanishing: Option<CellPosition>,
+    player: CellState,
+    (flat_pos, mark): Move,
+) -> MoveOutcome {
+    let line_length = rules.variant.line_length();
+    // A vanishing mark is already gone when the new one is checked for a line
+    let state_at = |pos: CellPosition| if Some(pos) == vanishing { CellState::None } else { state_at(pos) };
+    let mut outcome = MoveOutcome {
+        result: MoveResult::Continue,
+        claimed: None,
+        forced: None,
+        grown: Vec::new(),
+    };
+
+    if rules.variant == Variant::Ultimate {
+        let (sub_board, pos) = split_position(flat_pos);
+        // Lines stop at the edges of the small board
+        let small_state_at = |pos: CellPosition| {
+            if pos.row.abs() <= 1 && pos.col.abs() <= 1 {
+                state_at(flat_position(pos, Some(sub_board)))
+            } else {
+                CellState::None
+            }
+        };
+        let small_won = get_winning_positions(small_state_at, (mark, pos), line_length).is_some();
+        let small_full = (-1..=1).all(|row| (-1..=1).all(|col| {
+            let cell_pos = CellPosition { row, col, layer: 0 };
+            cell_pos == pos || small_state_at(cell_pos) != CellState::None
+        }));
+
+        if small_won {
+            outcome.claimed = Some((sub_board, player));
+        } else if small_full {
+            outcome.claimed = Some((sub_board, CellState::None));
+        }
+        let owner = |board_pos: CellPosition| match outcome.claimed {
+            Some((claimed, owner)) if claimed == board_pos => Some(owner),
+            _ => ultimate.owners.get(&board_pos).copied(),
+        };
+        // The opponent is sent to the small board matching the cell just played
+        outcome.forced = owner(pos).is_none().then_some(pos);
+
+        let winning_positions = if small_won {
+            get_winning_positions(|pos| owner(pos).unwrap_or(CellState::None), (player, sub_board), line_length)
+        } else {
+            None
+        };
+        // Moves are only legal on small boards nobody owns yet
+        let owned = ultimate.owners.len() + outcome.claimed.is_some() as usize;
+        outcome.result = if let Some(positions) = winning_positions {
+            MoveResult::Won(positions)
+        } else if owned == ultimate.boards.len() {
+            MoveResult::Draw
+        } else {
+            MoveResult::Continue
+        };
+        return outcome;
+    }
+
+    let cells: HashSet<_> = cells.into_iter().collect();
+    let winning_positions = get_winning_positions(state_at, (mark, flat_pos), line_length);
+    let full = cells.iter().all(|pos| *pos == flat_pos || state_at(*pos) != CellState::None);
+    outcome.result = if let Some(positions) = winning_positions {
+        MoveResult::Won(positions)
+    } else if full {
+        MoveResult::Draw
+    } else {
+        MoveResult::Continue
+    };
+    // Infinite boards grow around every mark
+    if rules.variant == Variant::Infinite {
+        outcome.grown = margin_cells(flat_pos, |pos| cells.contains(&pos));
+    }
+    outcome
+}
 
 pub fn update_game_state(
+    mut commands: Commands,
     mut next_game_state: ResMut<NextState<GameState>>,
     mut game_over_evt_wtr: EventWriter<GameOverEvent>,
+    mut sub_board_won_evt_wtr: EventWriter<SubBoardWonEvent>,
     mut cell_picked_evt_rdr: EventReader<CellPickedEvent>,
+    mut ultimate: ResMut<UltimateBoard>,
+    mut history: ResMut<MoveHistory>,
     curr_game_state: Res<State<GameState>>,
-    cell_qry: Query<&CellState>,
+    rules: Res<Rules>,
+    tex_atlas_handle: Res<TextureAtlasHandle>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+    cell_qry: Query<(&CellState, &CellPosition, Option<&SubBoard>)>,
+    placement_qry: Query<(Entity, &Placement)>,
     board: Res<Board>,
 ) {
+    let turn_mark = curr_game_state.0.turn_mark();
+    let board_state_at = |pos: CellPosition| {
+        board.0.get(&pos)
+            .and_then(|ent| cell_qry.get(*ent).ok())
+            .map_or(CellState::None, |(state, ..)| *state)
+    };
+
+    // Moves can come from the mouse and the turn clock in the same frame, only the first counts
+    let mut picked = None;
     for evt in cell_picked_evt_rdr.iter() {
-        let ent = evt.entity;
-        let state = evt.state;
-        let pos = evt.position;
-        let winning_positions = get_winning_positions(
-            &cell_qry,
-            (state, pos),
-            &board
-        );
-        if let Some(positions) = winning_positions {
+        let is_legal = cell_qry.get(evt.entity).map_or(false, |(state, _, sub_board)| {
+            is_legal_cell(&rules, &ultimate, *state, sub_board, evt.position, board_state_at)
+        });
+        // Only wild tic-tac-toe lets a player place the other side's mark
+        let is_own_mark = rules.wild || evt.state == evt.player;
+        if picked.is_none() && evt.player == turn_mark && is_own_mark && is_legal {
+            picked = Some(evt);
+        }
+    }
+    let Some(evt) = picked else { return; };
+
+    let ent = evt.entity;
+    let state = evt.state;
+    let player = evt.player;
+    let pos = evt.position;
+
+    let sub_board = cell_qry.get(ent).ok().and_then(|(.., sub_board)| sub_board);
+    let flat_pos = flat_position(pos, sub_board.map(|sub_board| sub_board.0));
+    history.0.push((player, flat_pos));
+
+    let order = placement_qry.iter().map(|(_, placement)| placement.order + 1).max().unwrap_or(0);
+    commands.entity(ent)
+        .insert(state)
+        .insert(Placement { player, order });
+    // Cube cells show their mark through their material instead
+    if !rules.variant.is_cube() {
+        let sprite_ent = spawn_mark_sprite(&mut commands, &rules, &tex_atlas_handle, &tex_atlas_indices, state, pos);
+        commands.entity(ent).add_child(sprite_ent);
+    }
+
+    let vanishing = if rules.variant == Variant::Disappearing {
+        vanishing_mark(placement_qry.iter(), player)
+    } else {
+        None
+    };
+    if let Some(vanishing) = vanishing {
+        commands.entity(vanishing)
+            .insert(CellState::None)
+            .remove::<Placement>()
+            .despawn_descendants();
+    }
+
+    // Ultimate cells live on the small boards, everything else on the board itself.
+    // The picked cell's new state is only applied at the end of the frame.
+    let flat_cells: Vec<_> = if rules.variant == Variant::Ultimate {
+        ultimate.boards.iter()
+            .flat_map(|(sub_board, small_board)| small_board.0.keys().map(|pos| flat_position(*pos, Some(*sub_board))))
+            .collect()
+    } else {
+        board.0.keys().copied().collect()
+    };
+    let flat_state_at = |flat_pos: CellPosition| {
+        let ent = if rules.variant == Variant::Ultimate {
+            let (sub_board, pos) = split_position(flat_pos);
+            ultimate.boards.get(&sub_board).and_then(|small_board| small_board.0.get(&pos))
+        } else {
+            board.0.get(&flat_pos)
+        };
+        ent.and_then(|ent| cell_qry.get(*ent).ok()).map_or(CellState::None, |(state, ..)| *state)
+    };
+    let outcome = apply_move(
+        &rules,
+        &ultimate,
+        flat_cells,
+        flat_state_at,
+        vanishing.and_then(|vanishing| cell_qry.get(vanishing).ok()).map(|(_, pos, _)| *pos),
+        player,
+        (flat_pos, state),
+    );
+
+    // Infinite boards are grown on screen by extend_infinite_board
+    if rules.variant == Variant::Ultimate {
+        if let Some((sub_board, winner)) = outcome.claimed {
+            ultimate.owners.insert(sub_board, winner);
+            if winner != CellState::None {
+                sub_board_won_evt_wtr.send(SubBoardWonEvent { sub_board, winner });
+            }
+        }
+        ultimate.forced = outcome.forced;
+    }
+
+    match outcome.result {
+        MoveResult::Won(positions) => {
+            let winner = line_winner(&rules, player);
             next_game_state.set(GameState::GameOver);
             game_over_evt_wtr.send(GameOverEvent {
-                last_picked_cell_ent: ent,
-                last_picked_cell_state: state,
-                winning_positions: positions,
+                winner,
+                winning_positions: Some(positions),
             });
-        } else {
+        },
+        MoveResult::Draw => {
+            next_game_state.set(GameState::GameOver);
+            game_over_evt_wtr.send(GameOverEvent {
+                winner: CellState::None,
+                winning_positions: None,
+            });
+        },
+        MoveResult::Continue => {
             let new_state = if curr_game_state.0 == GameState::XTurn {
                 GameState::OTurn
             } else {
                 GameState::XTurn
             };
             next_game_state.set(new_state);
-        }
+        },
     }
 }
 
-fn get_winning_positions(
-    cell_qry: &Query<&CellState>,
+pub fn spawn_mark_sprite(
+    commands: &mut Commands,
+    rules: &Rules,
+    tex_atlas_handle: &TextureAtlasHandle,
+    tex_atlas_indices: &TextureAtlasIndices,
+    state: CellState,
+    pos: CellPosition,
+) -> Entity {
+    let sprite_index = if state == CellState::X {
+        tex_atlas_indices.x
+    } else {
+        tex_atlas_indices.o
+    };
+    let (transform, appear_tween) = if rules.variant == Variant::Gravity {
+        // Fall from above the board, the cell's own space is about one unit per row
+        let rows_fallen = (pos.row - rules.variant.rows().start() + 1) as f32;
+        let from = Vec3::Y * rows_fallen * 1.05;
+        (Transform::from_translation(from).with_scale(Vec3::splat(0.05)), Tween::new(
+            TweenTarget::Translation { from, to: Vec3::ZERO },
+            Duration::from_millis(120 + rows_fallen as u64 * 40),
+            Ease::QuadIn,
+        ))
+    } else {
+        (Transform::from_scale(Vec3::ZERO), Tween::new(
+            TweenTarget::Scale { from: Vec3::ZERO, to: Vec3::splat(0.05) },
+            Duration::from_millis(200),
+            Ease::BackOut,
+        ))
+    };
+    commands.spawn(SpriteSheetBundle {
+        texture_atlas: tex_atlas_handle.0.clone_weak(),
+        sprite: TextureAtlasSprite::new(sprite_index),
+        transform,
+        ..default()
+    })
+        .insert(MarkSprite)
+        .insert(appear_tween)
+        .id()
+}
+
+pub fn get_winning_positions(
+    state_at: impl Fn(CellPosition) -> CellState,
     picked_cell: (CellState, CellPosition),
-    board: &Board,
-) -> Option<[CellPosition; 3]> {
-    let picked_state = picked_cell.0;
-    let picked_pos = picked_cell.1;
-
-    // Check horizontal
-    let mut game_over = false;
-    for col in -1..=1 {
-        if col == picked_pos.col {
-            if col == 1 {
-                game_over = true;
-                break;
-            }
-            continue;
+    line_length: usize,
+) -> Option<Vec<CellPosition>> {
+    let (picked_state, picked_pos) = picked_cell;
+    // The picked cell may not hold its new state yet
+    let is_picked_state = |pos: CellPosition| pos == picked_pos || state_at(pos) == picked_state;
+
+    // Check horizontal, vertical, left-right diagonal and right-left diagonal within the layer,
+    // then every line going through the layers including the space diagonals
+    let in_layer = [(0, 0, 1), (0, 1, 0), (0, 1, 1), (0, 1, -1)];
+    let across_layers = (-1..=1).flat_map(|d_row| (-1..=1).map(move |d_col| (1, d_row, d_col)));
+    for (d_layer, d_row, d_col) in in_layer.into_iter().chain(across_layers) {
+        let step = |pos: CellPosition, dir: i32| CellPosition {
+            row: pos.row + d_row * dir,
+            col: pos.col + d_col * dir,
+            layer: pos.layer + d_layer * dir,
+        };
+
+        let mut start = picked_pos;
+        loop {
+            let prev = step(start, -1);
+            if !is_picked_state(prev) { break; }
+            start = prev;
         }
 
-        let pos = CellPosition { row: picked_pos.row, col };
-        let ent = board.0.get(&pos).unwrap();
-        let state = cell_qry.get(*ent).unwrap();
-        if *state != picked_state {
-            break;
+        let mut line = vec![start];
+        loop {
+            let next = step(line[line.len() - 1], 1);
+            if !is_picked_state(next) { break; }
+            line.push(next);
         }
-        if col == 1 {
-            game_over = true;
-            break;
+
+        if line.len() >= line_length {
+            return Some(line);
         }
     }
-    if game_over {
-        let row = picked_pos.row;
-        return Some([
-            CellPosition { row, col: -1 },
-            CellPosition { row, col: 0 },
-            CellPosition { row, col: 1 },
-        ]);
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use super::*;
+
+    fn cell(row: i32, col: i32, layer: i32) -> CellPosition {
+        CellPosition { row, col, layer }
     }
-    
-    // Check vertical
-    game_over = false;
-    for row in -1..=1 {
-        if row == picked_pos.row {
-            if row == 1 {
-                game_over = true;
-                break;
-            }
-            continue;
-        }
 
-        let pos = CellPosition { row, col: picked_pos.col };
-        let ent = board.0.get(&pos).unwrap();
-        let state = cell_qry.get(*ent).unwrap();
-        if *state != picked_state {
-            break;
-        }
-        if row == 1 {
-            game_over = true;
-            break;
-        }
+    fn board(marks: &[(CellPosition, CellState)]) -> HashMap<CellPosition, CellState> {
+        marks.iter().copied().collect()
     }
-    if game_over {
-        let col = picked_pos.col;
-        return Some([
-            CellPosition { row: -1, col },
-            CellPosition { row: 0, col },
-            CellPosition { row: 1, col },
-        ]);
+
+    fn winning_positions(cells: &HashMap<CellPosition, CellState>, picked: (CellState, CellPosition), line_length: usize) -> Option<Vec<CellPosition>> {
+        get_winning_positions(|pos| cells.get(&pos).copied().unwrap_or(CellState::None), picked, line_length)
     }
-    
-    // Check left-right diagonal
-    game_over = false;
-    let mut col = -1;
-    for row in -1..=1 {
-        let pos = CellPosition { row, col };
-        if pos == picked_pos {
-            if row == 1 {
-                game_over = true;
-                break;
-            }
-            col += 1;
-            continue;
-        }
 
-        let ent = board.0.get(&pos).unwrap();
-        let state = cell_qry.get(*ent).unwrap();
-        if *state != picked_state {
-            break;
-        }
-        if row == 1 {
-            game_over = true;
-            break;
-        }
-        
-        col += 1;
-    }
-    if game_over {
-        return Some([
-            CellPosition { row: -1, col: -1 },
-            CellPosition { row: 0, col: 0 },
-            CellPosition { row: 1, col: 1 },
-        ]);
+    #[test]
+    fn finds_lines_through_the_picked_cell() {
+        use CellState::{O, X};
+        // The picked cell is still empty when the line is checked
+        let cells = board(&[(cell(0, -1, 0), X), (cell(0, 1, 0), X), (cell(-1, -1, 0), O)]);
+        assert_eq!(winning_positions(&cells, (X, cell(0, 0, 0)), 3), Some(vec![cell(0, -1, 0), cell(0, 0, 0), cell(0, 1, 0)]));
+        assert_eq!(winning_positions(&cells, (O, cell(0, 0, 0)), 3), None);
+
+        let cells = board(&[(cell(-1, -1, 0), O), (cell(1, 1, 0), O)]);
+        assert_eq!(winning_positions(&cells, (O, cell(0, 0, 0)), 3), Some(vec![cell(-1, -1, 0), cell(0, 0, 0), cell(1, 1, 0)]));
+    }
+
+    #[test]
+    fn needs_the_whole_line_length() {
+        use CellState::X;
+        let cells = board(&[(cell(2, -3, 0), X), (cell(2, -2, 0), X)]);
+        assert_eq!(winning_positions(&cells, (X, cell(2, -1, 0)), 4), None);
+        let cells = board(&[(cell(2, -3, 0), X), (cell(2, -2, 0), X), (cell(2, 0, 0), X)]);
+        assert_eq!(winning_positions(&cells, (X, cell(2, -1, 0)), 4).map(|line| line.len()), Some(4));
     }
-    
-    // Check right-left diagonal
-    game_over = false;
-    let mut col = 1;
-    for row in -1..=1 {
-        let pos = CellPosition { row, col };
-        if pos == picked_pos {
-            if row == 1 {
-                game_over = true;
-                break;
+
+    #[test]
+    fn finds_lines_across_layers() {
+        use CellState::X;
+        // Space diagonal of a 3x3x3 cube
+        let cells = board(&[(cell(-1, -1, -1), X), (cell(1, 1, 1), X)]);
+        assert_eq!(winning_positions(&cells, (X, cell(0, 0, 0)), 3), Some(vec![cell(-1, -1, -1), cell(0, 0, 0), cell(1, 1, 1)]));
+        // Straight down through the layers
+        let cells = board(&[(cell(1, 0, -1), X), (cell(1, 0, 0), X)]);
+        assert_eq!(winning_positions(&cells, (X, cell(1, 0, 1)), 3), Some(vec![cell(1, 0, -1), cell(1, 0, 0), cell(1, 0, 1)]));
+    }
+
+    fn ultimate() -> UltimateBoard {
+        let mut ultimate = UltimateBoard::default();
+        for row in -1..=1 {
+            for col in -1..=1 {
+                ultimate.boards.insert(cell(row, col, 0), Board::default());
             }
-            col -= 1;
-            continue;
         }
+        ultimate
+    }
 
-        let ent = board.0.get(&pos).unwrap();
-        let state = cell_qry.get(*ent).unwrap();
-        if *state != picked_state {
-            break;
-        }
-        if row == 1 {
-            game_over = true;
-            break;
-        }
-        
-        col -= 1;
-    }
-    if game_over {
-        return Some([
-            CellPosition { row: -1, col: 1 },
-            CellPosition { row: 0, col: 0 },
-            CellPosition { row: 1, col: -1 },
+    fn apply(rules: &Rules, ultimate: &UltimateBoard, cells: &HashMap<CellPosition, CellState>, player: CellState, pos: CellPosition) -> MoveOutcome {
+        let all_cells = (-4..=4).flat_map(|row| (-4..=4).map(move |col| cell(row, col, 0)));
+        apply_move(rules, ultimate, all_cells, |pos| cells.get(&pos).copied().unwrap_or(CellState::None), None, player, (pos, player))
+    }
+
+    #[test]
+    fn ultimate_moves_claim_small_boards() {
+        use CellState::{O, X};
+        let rules = Rules { variant: Variant::Ultimate, ..Rules::default() };
+        let mut ultimate = ultimate();
+
+        // Top row of the middle small board
+        let cells = board(&[(cell(-1, -1, 0), X), (cell(-1, 0, 0), X)]);
+        let outcome = apply(&rules, &ultimate, &cells, X, cell(-1, 1, 0));
+        assert_eq!(outcome.claimed, Some((cell(0, 0, 0), X)));
+        assert_eq!(outcome.forced, Some(cell(-1, 1, 0)));
+        assert!(matches!(outcome.result, MoveResult::Continue));
+
+        // Sent to a small board that is already taken, the opponent may play anywhere
+        ultimate.owners.insert(cell(-1, 1, 0), O);
+        let outcome = apply(&rules, &ultimate, &cells, X, cell(-1, 1, 0));
+        assert_eq!(outcome.forced, None);
+
+        // Filling the middle small board without a line draws it
+        let ultimate = self::ultimate();
+        let cells = board(&[
+            (cell(-1, -1, 0), X), (cell(-1, 0, 0), O), (cell(-1, 1, 0), X),
+            (cell(0, -1, 0), X), (cell(0, 0, 0), O), (cell(0, 1, 0), O),
+            (cell(1, -1, 0), O), (cell(1, 0, 0), X),
         ]);
+        let outcome = apply(&rules, &ultimate, &cells, X, cell(1, 1, 0));
+        assert_eq!(outcome.claimed, Some((cell(0, 0, 0), CellState::None)));
+        assert!(matches!(outcome.result, MoveResult::Continue));
     }
-    
-    None
-}
\ No newline at end of file
+
+    #[test]
+    fn infinite_boards_grow_around_marks() {
+        let rules = Rules { variant: Variant::Infinite, ..Rules::default() };
+        let cells: Vec<_> = Variant::Infinite.rows()
+            .flat_map(|row| Variant::Infinite.cols().map(move |col| cell(row, col, 0)))
+            .collect();
+        let outcome = apply_move(&rules, &UltimateBoard::default(), cells.clone(), |_| CellState::None, None, CellState::X, (cell(0, 3, 0), CellState::X));
+        // Three more columns to the right of the seven rows
+        assert_eq!(outcome.grown.len(), 3 * 7);
+        assert!(outcome.grown.iter().all(|pos| !cells.contains(pos) && (4..=6).contains(&pos.col)));
+    }
+}
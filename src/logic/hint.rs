@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+
+use crate::data::*;
+use super::{explain_suggestion, snapshot, suggest_move};
+
+pub fn give_hint(
+    mut hint_requested_evt_rdr: EventReader<HintRequestedEvent>,
+    mut hint_given_evt_wtr: EventWriter<HintGivenEvent>,
+    mut score: ResMut<MatchScore>,
+    players: Res<Players>,
+    game_state: Res<State<GameState>>,
+    rules: Res<Rules>,
+    ultimate: Res<UltimateBoard>,
+    board: Res<Board>,
+    cell_qry: Query<(Entity, &CellState, &CellPosition, Option<&SubBoard>)>,
+    placement_qry: Query<(Entity, &Placement)>,
+    hinted_qry: Query<(), With<HintedCell>>,
+    seating: Res<Seating>,
+) {
+    if hint_requested_evt_rdr.iter().count() == 0 { return; }
+
+    let player = game_state.0.turn_mark();
+    if player == CellState::None || !players.is_human(player) { return; }
+
+    let (position, entities) = snapshot(&rules, &ultimate, &board, &cell_qry, &placement_qry);
+    let Some((pos, mark)) = suggest_move(player, &rules, &position) else { return; };
+    let Some(ent) = entities.get(&pos) else { return; };
+    let reason = explain_suggestion(pos, mark, player, &rules, &position);
+
+    // Asking again for the cell already shown costs nothing
+    if let Some(person) = seating.person(player).filter(|_| !hinted_qry.contains(*ent)) {
+        score.hints[person] += 1;
+    }
+    hint_given_evt_wtr.send(HintGivenEvent {
+        entity: *ent,
+        state: mark,
+        reason,
+    });
+}
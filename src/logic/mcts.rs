@@ -0,0 +1,237 @@
+use bevy::utils::{HashMap, Instant};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::data::*;
+use super::{get_winning_positions, line_winner};
+
+// Copy of the board the search runs on, detached from the world so it can leave the main thread
+#[derive(Clone)]
+pub struct SearchPosition {
+    pub cells: HashMap<CellPosition, CellState>,
+    pub rules: Rules,
+    // Side to move
+    pub player: CellState,
+}
+
+// Infinite boards only consider cells this close to a mark, the rest of the board is noise
+const INFINITE_REACH: i32 = 2;
+
+impl SearchPosition {
+    fn state_at(&self, pos: CellPosition) -> CellState {
+        self.cells.get(&pos).copied().unwrap_or(CellState::None)
+    }
+
+    fn marks(&self, side: CellState) -> &'static [CellState] {
+        if self.rules.wild {
+            &[CellState::X, CellState::O]
+        } else if side == CellState::X {
+            &[CellState::X]
+        } else {
+            &[CellState::O]
+        }
+    }
+
+    // Cells the search looks at, fixed for the whole search so playouts stay cheap
+    fn area(&self) -> Vec<CellPosition> {
+        let mut area: Vec<_> = self.cells.keys()
+            .filter(|pos| self.rules.variant != Variant::Infinite || self.cells.iter().any(|(mark_pos, state)| {
+                *state != CellState::None
+                    && (mark_pos.row - pos.row).abs() <= INFINITE_REACH
+                    && (mark_pos.col - pos.col).abs() <= INFINITE_REACH
+            }))
+            .copied()
+            .collect();
+        if area.is_empty() {
+            area.push(CellPosition { row: 0, col: 0, layer: 0 });
+        }
+        // Sorted so equally good moves are tried in the same order every time
+        area.sort_by_key(|pos| (pos.layer, pos.row, pos.col));
+        area
+    }
+
+    fn legal_moves(&self, area: &[CellPosition], side: CellState) -> Vec<Move> {
+        area.iter()
+            .filter(|pos| self.state_at(**pos) == CellState::None)
+            .filter(|pos| {
+                self.rules.variant != Variant::Gravity
+                    || landing_cell(self.rules.variant, pos.col, |pos| self.state_at(pos)) == Some(**pos)
+            })
+            .flat_map(|pos| self.marks(side).iter().map(move |mark| (*pos, *mark)))
+            .collect()
+    }
+
+    // Plays the move, returning the winner if it ended the game with a line
+    fn play(&mut self, (pos, mark): Move) -> Option<CellState> {
+        let completes_line = get_winning_positions(
+            |pos| self.state_at(pos),
+            (mark, pos),
+            self.rules.variant.line_length(),
+        ).is_some();
+        let side = self.player;
+        self.cells.insert(pos, mark);
+        self.player = side.opponent();
+        completes_line.then(|| line_winner(&self.rules, side))
+    }
+}
+
+struct Node {
+    parent: Option<usize>,
+    // Move leading here, made by `side`
+    mv: Option<Move>,
+    side: CellState,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    // Wins for `side`, draws count half
+    reward: f32,
+    // The game is over once this node's move is made
+    terminal: Option<CellState>,
+}
+
+// Upper confidence bound of a child, `exploration` trades trying new moves against
+// playing the ones that went well
+fn uct(child: &Node, parent_visits: u32, exploration: f32) -> f32 {
+    let visits = child.visits as f32;
+    child.reward / visits + exploration * ((parent_visits as f32).ln() / visits).sqrt()
+}
+
+// Monte Carlo tree search from `root`, stopping after the iteration or time budget runs out,
+// whichever comes first. Returns the most visited move.
+pub fn mcts_move(root: SearchPosition, settings: &MctsSettings, rng: &mut impl Rng) -> Option<Move> {
+    let area = root.area();
+    let mut nodes = vec![Node {
+        parent: None,
+        mv: None,
+        side: root.player.opponent(),
+        children: Vec::new(),
+        untried: root.legal_moves(&area, root.player),
+        visits: 0,
+        reward: 0.,
+        terminal: None,
+    }];
+
+    let started = Instant::now();
+    for _ in 0..settings.iterations {
+        if started.elapsed() >= settings.time_budget { break; }
+
+        // Selection, down the tree through fully expanded nodes
+        let mut position = root.clone();
+        let mut node = 0;
+        while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() && nodes[node].terminal.is_none() {
+            let parent_visits = nodes[node].visits;
+            node = *nodes[node].children.iter()
+                .max_by(|a, b| {
+                    uct(&nodes[**a], parent_visits, settings.exploration)
+                        .total_cmp(&uct(&nodes[**b], parent_visits, settings.exploration))
+                })
+                .unwrap();
+            position.play(nodes[node].mv.unwrap());
+        }
+
+        // Expansion, one new child for a move not tried yet
+        if nodes[node].terminal.is_none() && !nodes[node].untried.is_empty() {
+            let index = rng.gen_range(0..nodes[node].untried.len());
+            let mv = nodes[node].untried.swap_remove(index);
+            let side = position.player;
+            let terminal = position.play(mv);
+            let untried = match terminal {
+                Some(_) => Vec::new(),
+                None => position.legal_moves(&area, position.player),
+            };
+            nodes.push(Node {
+                parent: Some(node),
+                mv: Some(mv),
+                side,
+                children: Vec::new(),
+                untried,
+                visits: 0,
+                reward: 0.,
+                terminal,
+            });
+            let child = nodes.len() - 1;
+            nodes[node].children.push(child);
+            node = child;
+        }
+
+        // Simulation, random moves until someone completes a line or the cells run out
+        let mut winner = nodes[node].terminal;
+        while winner.is_none() {
+            let moves = position.legal_moves(&area, position.player);
+            let Some(mv) = moves.choose(rng) else { break; };
+            winner = position.play(*mv);
+        }
+
+        // Backpropagation
+        let mut current = Some(node);
+        while let Some(index) = current {
+            let node = &mut nodes[index];
+            node.visits += 1;
+            node.reward += match winner {
+                Some(winner) if winner == node.side => 1.,
+                Some(_) => 0.,
+                None => 0.5,
+            };
+            current = node.parent;
+        }
+    }
+
+    nodes[0].children.iter()
+        .max_by_key(|child| nodes[**child].visits)
+        .and_then(|child| nodes[*child].mv)
+        .or_else(|| nodes[0].untried.first().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn position(variant: Variant, player: CellState, marks: &[((i32, i32), CellState)]) -> SearchPosition {
+        let mut cells = HashMap::new();
+        for row in variant.rows() {
+            for col in variant.cols() {
+                cells.insert(CellPosition { row, col, layer: 0 }, CellState::None);
+            }
+        }
+        for ((row, col), state) in marks {
+            cells.insert(CellPosition { row: *row, col: *col, layer: 0 }, *state);
+        }
+        SearchPosition { cells, rules: Rules { variant, misere: false, wild: false }, player }
+    }
+
+    // Seeded so every run searches the same playouts
+    fn search(root: SearchPosition) -> Option<Move> {
+        let settings = MctsSettings { iterations: 5_000, time_budget: Duration::from_secs(30), ..MctsSettings::default() };
+        mcts_move(root, &settings, &mut StdRng::seed_from_u64(7))
+    }
+
+    #[test]
+    fn takes_a_winning_move() {
+        use CellState::{O, X};
+        // X X .
+        // O O .
+        // . . .
+        let root = position(Variant::Classic, X, &[((-1, -1), X), ((-1, 0), X), ((0, -1), O), ((0, 0), O)]);
+        assert_eq!(search(root), Some((CellPosition { row: -1, col: 1, layer: 0 }, X)));
+    }
+
+    #[test]
+    fn blocks_a_losing_move() {
+        use CellState::{O, X};
+        // Gravity, O is about to drop a fourth mark into the bottom row
+        let root = position(Variant::Gravity, X, &[((2, -3), O), ((2, -2), O), ((2, -1), O), ((1, -3), X), ((1, -2), X)]);
+        assert_eq!(search(root), Some((CellPosition { row: 2, col: 0, layer: 0 }, X)));
+    }
+
+    #[test]
+    fn returns_none_without_legal_moves() {
+        use CellState::{O, X};
+        let marks: Vec<_> = (-1..=1)
+            .flat_map(|row| (-1..=1).map(move |col| ((row, col), if (row + col) % 2 == 0 { X } else { O })))
+            .collect();
+        assert_eq!(search(position(Variant::Classic, X, &marks)), None);
+    }
+}
@@ -8,15 +8,129 @@ pub use gameover::*;
 mod restart;
 pub use restart::*;
 
+mod clock;
+pub use clock::*;
+
+mod ai;
+pub use ai::*;
+
+mod mcts;
+pub use mcts::*;
+
+mod players;
+pub use players::*;
+
+mod engine;
+pub use engine::*;
+
+mod game;
+pub use game::*;
+
+mod profiles;
+pub use profiles::*;
+
+mod rating;
+pub use rating::*;
+
+mod achievements;
+pub use achievements::*;
+
+mod tutorial;
+pub use tutorial::*;
+
+mod start;
+pub use start::*;
+
+mod hint;
+pub use hint::*;
+
+mod eval;
+pub use eval::*;
+
 pub struct LogicPlugin;
 
 impl Plugin for LogicPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_event::<GameOverEvent>()
+            .add_event::<NewGameEvent>()
+            .add_event::<BoardClearedEvent>()
+            .add_event::<SubBoardWonEvent>()
+            .init_resource::<Rules>()
+            .init_resource::<UltimateBoard>()
             .add_state::<GameState>()
             .add_system(update_game_state)
             .add_system(highlight_winning_cells.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(restart_game.in_set(OnUpdate(GameState::GameOver)))
+            .add_system(change_variant)
+            .add_system(toggle_misere)
+            .init_resource::<MarkChoice>()
+            .add_system(toggle_wild)
+            .add_system(swap_mark_choice)
+            .init_resource::<StartSettings>()
+            .init_resource::<Round>()
+            .add_system(change_first_player)
+            .add_system(toggle_pie_rule)
+            .add_system(record_round_result)
+            .init_resource::<MatchScore>()
+            .init_resource::<Seating>()
+            .add_system(update_match_score)
+            .init_resource::<MoveHistory>()
+            .init_resource::<Profiles>()
+            .add_startup_system(load_profiles)
+            .add_system(rate_game)
+            .add_event::<AchievementUnlockedEvent>()
+            .add_system(unlock_achievements.after(rate_game))
+            .add_event::<NextProfileEvent>()
+            .add_event::<NextMarkColorEvent>()
+            .add_event::<NextAvatarEvent>()
+            .add_system(next_profile)
+            .add_system(next_mark_color)
+            .add_system(next_avatar)
+            .add_event::<HintGivenEvent>()
+            .add_system(give_hint)
+            .init_resource::<EvalOverlay>()
+            .add_system(toggle_eval_overlay)
+            .add_system(evaluate_position.after(toggle_eval_overlay))
+            .add_system(swap_sides)
+            .init_resource::<Tutorial>()
+            // Lessons start, and their boards are cleared and loaded, within one frame
+            .add_system(toggle_tutorial.before(reset_game))
+            .add_system(stop_tutorial.after(toggle_tutorial))
+            .add_system(load_tutorial_step.after(reset_game))
+            .add_system(check_tutorial_move)
+            .add_system(advance_tutorial.after(check_tutorial_move).before(reset_game))
+            .add_system(reset_game
+                .after(restart_game)
+                .after(change_variant)
+                .after(toggle_misere)
+                .after(toggle_wild)
+                .after(change_first_player)
+                .after(toggle_pie_rule)
+                .after(record_round_result))
+            .insert_resource(Players {
+                x: Box::new(HumanController),
+                o: Box::new(HumanController),
+            })
+            .init_resource::<MctsSettings>()
+            .init_resource::<Difficulty>()
+            .add_startup_system(load_engines)
+            .add_system(start_player_game)
+            .add_system(toggle_ai)
+            .add_system(next_difficulty)
+            .add_system(start_player_turn.in_schedule(OnEnter(GameState::XTurn)))
+            .add_system(start_player_turn.in_schedule(OnEnter(GameState::OTurn)))
+            .add_system(request_move.in_set(OnUpdate(GameState::XTurn)))
+            .add_system(request_move.in_set(OnUpdate(GameState::OTurn)))
+            .add_system(quit_game)
+            .init_resource::<ClockSettings>()
+            .init_resource::<TurnClock>()
+            .add_system(change_clock_mode)
+            .add_system(reset_turn_clock.after(change_clock_mode))
+            .add_system(start_move_clock.in_schedule(OnEnter(GameState::XTurn)))
+            .add_system(start_move_clock.in_schedule(OnEnter(GameState::OTurn)))
+            .add_system(tick_turn_clock.in_set(OnUpdate(GameState::XTurn)))
+            .add_system(tick_turn_clock.in_set(OnUpdate(GameState::OTurn)))
             .register_type::<CellState>()
             .register_type::<CellPosition>();
     }
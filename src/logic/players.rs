@@ -0,0 +1,109 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::data::*;
+use super::is_legal_cell;
+
+// Plays whatever the local player clicks
+pub struct HumanController;
+
+impl Controller for HumanController {
+    fn is_human(&self) -> bool {
+        true
+    }
+
+    fn poll_move(&mut self, request: &MoveRequest) -> Option<Move> {
+        request.local_move
+    }
+}
+
+// Position handed to controllers, along with the cell entity of every flat position
+pub fn snapshot(
+    rules: &Rules,
+    ultimate: &UltimateBoard,
+    board: &Board,
+    cell_qry: &Query<(Entity, &CellState, &CellPosition, Option<&SubBoard>)>,
+    placement_qry: &Query<(Entity, &Placement)>,
+) -> (Position, HashMap<CellPosition, Entity>) {
+    let state_at = |pos: CellPosition| {
+        board.0.get(&pos)
+            .and_then(|ent| cell_qry.get(*ent).ok())
+            .map_or(CellState::None, |(_, state, ..)| *state)
+    };
+
+    let mut position = Position::default();
+    let mut entities = HashMap::new();
+    for (ent, state, pos, sub_board) in cell_qry.iter() {
+        let flat_pos = flat_position(*pos, sub_board.map(|sub_board| sub_board.0));
+        position.cells.insert(flat_pos, *state);
+        entities.insert(flat_pos, ent);
+        if is_legal_cell(rules, ultimate, *state, sub_board, *pos, state_at) {
+            position.legal.push(flat_pos);
+        }
+    }
+    if rules.variant == Variant::Disappearing {
+        for side in [CellState::X, CellState::O] {
+            let vanishing = vanishing_mark(placement_qry.iter(), side)
+                .and_then(|ent| cell_qry.get(ent).ok());
+            if let Some((_, _, pos, _)) = vanishing {
+                position.vanishing.insert(side, *pos);
+            }
+        }
+    }
+    (position, entities)
+}
+
+pub fn start_player_game(
+    mut new_game_evt_rdr: EventReader<NewGameEvent>,
+    mut players: ResMut<Players>,
+) {
+    if new_game_evt_rdr.iter().count() == 0 { return; }
+    players.x.new_game();
+    players.o.new_game();
+}
+
+pub fn start_player_turn(
+    mut players: ResMut<Players>,
+    game_state: Res<State<GameState>>,
+) {
+    players.get_mut(game_state.0.turn_mark()).start_turn();
+}
+
+// Asks the controller of the side to move for a move, every frame until it comes up with one
+pub fn request_move(
+    mut local_move_evt_rdr: EventReader<LocalMoveEvent>,
+    mut cell_picked_evt_wtr: EventWriter<CellPickedEvent>,
+    mut players: ResMut<Players>,
+    game_state: Res<State<GameState>>,
+    rules: Res<Rules>,
+    ultimate: Res<UltimateBoard>,
+    board: Res<Board>,
+    cell_qry: Query<(Entity, &CellState, &CellPosition, Option<&SubBoard>)>,
+    placement_qry: Query<(Entity, &Placement)>,
+    time: Res<Time>,
+) {
+    let player = game_state.0.turn_mark();
+    let (position, entities) = snapshot(&rules, &ultimate, &board, &cell_qry, &placement_qry);
+    let local_move = local_move_evt_rdr.iter()
+        .filter_map(|evt| {
+            let (_, _, pos, sub_board) = cell_qry.get(evt.entity).ok()?;
+            Some((flat_position(*pos, sub_board.map(|sub_board| sub_board.0)), evt.state))
+        })
+        .last();
+
+    let request = MoveRequest {
+        player,
+        rules: *rules,
+        position: &position,
+        local_move,
+        delta: time.delta(),
+    };
+    let Some((flat_pos, mark)) = players.get_mut(player).poll_move(&request) else { return; };
+    let Some(ent) = entities.get(&flat_pos) else { return; };
+    let Ok((_, _, pos, _)) = cell_qry.get(*ent) else { return; };
+    cell_picked_evt_wtr.send(CellPickedEvent {
+        entity: *ent,
+        state: mark,
+        player,
+        position: *pos,
+    });
+}
@@ -0,0 +1,97 @@
+use std::fs;
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+
+use crate::data::*;
+
+// Name of the profile rated for a side's games
+pub fn side_profile(players: &Players, profiles: &Profiles, side: CellState) -> String {
+    players.get(side).profile().unwrap_or_else(|| profiles.seat(side).to_string())
+}
+
+pub fn save_profiles(profiles: &Profiles) {
+    let saved = ron::ser::to_string_pretty(profiles, PrettyConfig::default())
+        .map_err(|err| err.to_string())
+        .and_then(|ron| fs::write(PROFILES_PATH, ron).map_err(|err| err.to_string()));
+    if let Err(err) = saved {
+        warn!("failed to save profiles to {}: {}", PROFILES_PATH, err);
+    }
+}
+
+// `--x-profile <name>` and `--o-profile <name>` seat a profile at a side, creating it if needed
+pub fn load_profiles(mut profiles: ResMut<Profiles>) {
+    // Without a saved file the default profiles are used
+    if let Ok(ron) = fs::read_to_string(PROFILES_PATH) {
+        match ron::from_str(&ron) {
+            Ok(loaded) => *profiles = loaded,
+            Err(err) => warn!("failed to read profiles from {}: {}", PROFILES_PATH, err),
+        }
+    }
+
+    let args: Vec<String> = std::env::args().collect();
+    for (side, flag) in [(CellState::X, "--x-profile"), (CellState::O, "--o-profile")] {
+        let Some(name) = args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1)) else { continue; };
+        profiles.get_or_create(name);
+        *profiles.seat_mut(side) = name.clone();
+    }
+    // Seats may name profiles that were renamed or removed by hand
+    for side in [CellState::X, CellState::O] {
+        let name = profiles.seat(side).to_string();
+        profiles.get_or_create(&name);
+    }
+}
+
+// Seats the next local profile at a side, before the game's first move. Profiles seated at the
+// other side are skipped, a profile can't be rated against itself.
+pub fn next_profile(
+    mut next_profile_evt_rdr: EventReader<NextProfileEvent>,
+    mut profiles: ResMut<Profiles>,
+    players: Res<Players>,
+    history: Res<MoveHistory>,
+) {
+    for evt in next_profile_evt_rdr.iter() {
+        let side = evt.0;
+        if !history.0.is_empty() || players.get(side).profile().is_some() { continue; }
+
+        let other = profiles.seat(side.opponent()).to_string();
+        let seatable: Vec<String> = profiles.profiles.iter()
+            .filter(|profile| profile.is_local() && profile.name != other)
+            .map(|profile| profile.name.clone())
+            .collect();
+        let curr = seatable.iter().position(|name| name == profiles.seat(side));
+        let Some(next) = seatable.get(curr.map_or(0, |curr| (curr + 1) % seatable.len())) else { continue; };
+        info!("{:?} is played by {}", side, next);
+        *profiles.seat_mut(side) = next.clone();
+        save_profiles(&profiles);
+    }
+}
+
+pub fn next_mark_color(
+    mut next_mark_color_evt_rdr: EventReader<NextMarkColorEvent>,
+    mut profiles: ResMut<Profiles>,
+    players: Res<Players>,
+) {
+    for evt in next_mark_color_evt_rdr.iter() {
+        let name = side_profile(&players, &profiles, evt.0);
+        let profile = profiles.get_or_create(&name);
+        let curr = MARK_COLORS.iter().position(|color| *color == profile.color);
+        profile.color = MARK_COLORS[curr.map_or(0, |curr| (curr + 1) % MARK_COLORS.len())].to_string();
+        save_profiles(&profiles);
+    }
+}
+
+pub fn next_avatar(
+    mut next_avatar_evt_rdr: EventReader<NextAvatarEvent>,
+    mut profiles: ResMut<Profiles>,
+    players: Res<Players>,
+) {
+    for evt in next_avatar_evt_rdr.iter() {
+        let name = side_profile(&players, &profiles, evt.0);
+        let profile = profiles.get_or_create(&name);
+        let avatars = Avatar::ALL;
+        let curr = avatars.iter().position(|avatar| *avatar == profile.avatar).unwrap_or(0);
+        profile.avatar = avatars[(curr + 1) % avatars.len()];
+        save_profiles(&profiles);
+    }
+}
@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+
+use crate::data::*;
+use super::{save_profiles, side_profile};
+
+// Both profiles are rated for the variant after every finished game, a profile playing itself is left alone
+pub fn rate_game(
+    mut game_over_evt_rdr: EventReader<GameOverEvent>,
+    mut profiles: ResMut<Profiles>,
+    players: Res<Players>,
+    rules: Res<Rules>,
+    history: Res<MoveHistory>,
+) {
+    for evt in game_over_evt_rdr.iter() {
+        let x_name = side_profile(&players, &profiles, CellState::X);
+        let o_name = side_profile(&players, &profiles, CellState::O);
+        if x_name == o_name { continue; }
+
+        let x_rating = profiles.get_or_create(&x_name).rating(rules.variant);
+        let o_rating = profiles.get_or_create(&o_name).rating(rules.variant);
+        for (side, name, opponent, rating, opponent_rating) in [
+            (CellState::X, &x_name, &o_name, x_rating, o_rating),
+            (CellState::O, &o_name, &x_name, o_rating, x_rating),
+        ] {
+            let outcome = if evt.winner == side {
+                Outcome::Win
+            } else if evt.winner == CellState::None {
+                Outcome::Draw
+            } else {
+                Outcome::Loss
+            };
+            let opening = history.0.iter()
+                .find(|(player, _)| *player == side)
+                .map(|(_, pos)| *pos);
+            let new_rating = rating + rating_change(rating, opponent_rating, outcome);
+            profiles.get_or_create(name).history.push(GameRecord {
+                opponent: opponent.clone(),
+                variant: rules.variant,
+                outcome,
+                rating: new_rating,
+                moves: history.0.len() as u32,
+                opening,
+            });
+            info!("{} is now rated {:.0} at {:?}", name, new_rating, rules.variant);
+        }
+        save_profiles(&profiles);
+    }
+}
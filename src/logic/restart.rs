@@ -1,11 +1,100 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, app::AppExit};
 
-use crate::data::PlayBtnClickedEvt;
+use crate::data::*;
+use super::first_player;
 
 pub fn restart_game(
-    mut play_btn_evt_rdr: EventReader<PlayBtnClickedEvt>
+    mut play_btn_evt_rdr: EventReader<PlayBtnClickedEvt>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
 ) {
-    for evt in play_btn_evt_rdr.iter() {
-        println!("play button clicked");
+    if play_btn_evt_rdr.iter().count() > 0 {
+        new_game_evt_wtr.send(NewGameEvent);
     }
-}
\ No newline at end of file
+}
+
+pub fn change_variant(
+    mut next_variant_evt_rdr: EventReader<NextVariantEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut rules: ResMut<Rules>,
+) {
+    for _ in next_variant_evt_rdr.iter() {
+        let variants = Variant::ALL;
+        let curr = variants.iter().position(|variant| *variant == rules.variant).unwrap_or(0);
+        rules.variant = variants[(curr + 1) % variants.len()];
+        new_game_evt_wtr.send(NewGameEvent);
+    }
+}
+
+pub fn toggle_misere(
+    mut toggle_misere_evt_rdr: EventReader<ToggleMisereEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut rules: ResMut<Rules>,
+) {
+    for _ in toggle_misere_evt_rdr.iter() {
+        rules.misere = !rules.misere;
+        new_game_evt_wtr.send(NewGameEvent);
+    }
+}
+
+pub fn toggle_wild(
+    mut toggle_wild_evt_rdr: EventReader<ToggleWildEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut rules: ResMut<Rules>,
+) {
+    for _ in toggle_wild_evt_rdr.iter() {
+        rules.wild = !rules.wild;
+        new_game_evt_wtr.send(NewGameEvent);
+    }
+}
+
+pub fn swap_mark_choice(
+    mut swap_mark_choice_evt_rdr: EventReader<SwapMarkChoiceEvent>,
+    mut mark_choice: ResMut<MarkChoice>,
+    rules: Res<Rules>,
+) {
+    for _ in swap_mark_choice_evt_rdr.iter() {
+        if rules.wild {
+            mark_choice.0 = mark_choice.0.opponent();
+        }
+    }
+}
+
+pub fn reset_game(
+    mut commands: Commands,
+    mut new_game_evt_rdr: EventReader<NewGameEvent>,
+    mut board_cleared_evt_wtr: EventWriter<BoardClearedEvent>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut ultimate: ResMut<UltimateBoard>,
+    mut mark_choice: ResMut<MarkChoice>,
+    mut round: ResMut<Round>,
+    mut history: ResMut<MoveHistory>,
+    mut cell_qry: Query<(Entity, &mut CellState)>,
+    start_settings: Res<StartSettings>,
+    seating: Res<Seating>,
+) {
+    if new_game_evt_rdr.iter().count() == 0 { return; }
+
+    for (ent, mut state) in cell_qry.iter_mut() {
+        *state = CellState::None;
+        commands.entity(ent).remove::<Placement>();
+    }
+    ultimate.owners.clear();
+    ultimate.forced = None;
+    *mark_choice = MarkChoice::default();
+    history.0.clear();
+
+    let starter = first_player(&start_settings, &round, &seating);
+    round.starter = starter;
+    round.swapped = false;
+    next_game_state.set(GameState::turn_of(starter));
+    board_cleared_evt_wtr.send(BoardClearedEvent);
+}
+
+pub fn quit_game(
+    mut quit_btn_evt_rdr: EventReader<QuitBtnClickedEvt>,
+    mut app_exit_evt_wtr: EventWriter<AppExit>,
+) {
+    if quit_btn_evt_rdr.iter().count() > 0 {
+        app_exit_evt_wtr.send(AppExit);
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::data::*;
+
+pub fn change_first_player(
+    mut next_first_player_evt_rdr: EventReader<NextFirstPlayerEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut settings: ResMut<StartSettings>,
+) {
+    for _ in next_first_player_evt_rdr.iter() {
+        let options = FirstPlayer::ALL;
+        let curr = options.iter().position(|option| *option == settings.first_player).unwrap_or(0);
+        settings.first_player = options[(curr + 1) % options.len()];
+        info!("first player: {:?}", settings.first_player);
+        new_game_evt_wtr.send(NewGameEvent);
+    }
+}
+
+pub fn toggle_pie_rule(
+    mut toggle_pie_rule_evt_rdr: EventReader<TogglePieRuleEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut settings: ResMut<StartSettings>,
+) {
+    for _ in toggle_pie_rule_evt_rdr.iter() {
+        settings.pie_rule = !settings.pie_rule;
+        info!("pie rule: {}", settings.pie_rule);
+        new_game_evt_wtr.send(NewGameEvent);
+    }
+}
+
+pub fn record_round_result(
+    mut game_over_evt_rdr: EventReader<GameOverEvent>,
+    mut round: ResMut<Round>,
+    seating: Res<Seating>,
+) {
+    for evt in game_over_evt_rdr.iter() {
+        round.loser = seating.person(evt.winner.opponent());
+    }
+}
+
+pub fn update_match_score(
+    mut game_over_evt_rdr: EventReader<GameOverEvent>,
+    mut score: ResMut<MatchScore>,
+    seating: Res<Seating>,
+) {
+    for evt in game_over_evt_rdr.iter() {
+        match seating.person(evt.winner) {
+            Some(person) => score.wins[person] += 1,
+            None => score.draws += 1,
+        }
+    }
+}
+
+pub fn first_player(settings: &StartSettings, round: &Round, seating: &Seating) -> CellState {
+    match settings.first_player {
+        FirstPlayer::X => CellState::X,
+        FirstPlayer::O => CellState::O,
+        FirstPlayer::Alternate => round.starter.opponent(),
+        FirstPlayer::LoserStarts => match round.loser {
+            Some(loser) => seating.marks[loser],
+            None => round.starter.opponent(),
+        },
+        FirstPlayer::Random => *[CellState::X, CellState::O].choose(&mut rand::thread_rng()).unwrap(),
+    }
+}
+
+// The swap is offered to the second player on their first turn only
+pub fn can_swap_sides(settings: &StartSettings, round: &Round, game_state: &GameState, moves_played: usize) -> bool {
+    settings.pie_rule
+        && !round.swapped
+        && moves_played == 1
+        && game_state.turn_mark() == round.starter.opponent()
+}
+
+// Swapping keeps the board and the turn, only who plays which mark changes hands
+pub fn swap_sides(
+    mut swap_sides_evt_rdr: EventReader<SwapSidesEvent>,
+    mut round: ResMut<Round>,
+    mut seating: ResMut<Seating>,
+    mut players: ResMut<Players>,
+    mut profiles: ResMut<Profiles>,
+    settings: Res<StartSettings>,
+    game_state: Res<State<GameState>>,
+    placement_qry: Query<&Placement>,
+) {
+    for _ in swap_sides_evt_rdr.iter() {
+        if !can_swap_sides(&settings, &round, &game_state.0, placement_qry.iter().count()) { continue; }
+        if !players.is_human(game_state.0.turn_mark()) { continue; }
+
+        round.swapped = true;
+        seating.swap();
+        let players = &mut *players;
+        std::mem::swap(&mut players.x, &mut players.o);
+        let profiles = &mut *profiles;
+        std::mem::swap(&mut profiles.x, &mut profiles.o);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::HumanController;
+
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app
+            .add_state::<GameState>()
+            .add_event::<SwapSidesEvent>()
+            .add_event::<GameOverEvent>()
+            .insert_resource(StartSettings { first_player: FirstPlayer::LoserStarts, pie_rule: true })
+            .insert_resource(Players {
+                x: Box::new(HumanController),
+                o: Box::new(HumanController),
+            })
+            .init_resource::<Profiles>()
+            .init_resource::<Round>()
+            .init_resource::<Seating>()
+            .init_resource::<MatchScore>()
+            .add_systems((swap_sides, record_round_result.after(swap_sides), update_match_score.after(swap_sides)));
+        app
+    }
+
+    // X opens, the person playing O takes the pie rule and goes on to win with X
+    #[test]
+    fn swapped_people_keep_their_results() {
+        let mut app = app();
+        app.world.insert_resource(State(GameState::OTurn));
+        app.world.spawn(Placement { player: CellState::X, order: 0 });
+
+        app.world.send_event(SwapSidesEvent);
+        app.update();
+        assert_eq!(app.world.resource::<Seating>().marks, [CellState::O, CellState::X]);
+        assert_eq!(app.world.resource::<Profiles>().x, "Player 2");
+        assert!(app.world.resource::<Round>().swapped);
+
+        app.world.send_event(GameOverEvent { winner: CellState::X, winning_positions: None });
+        app.update();
+        assert_eq!(app.world.resource::<MatchScore>().wins, [0, 1]);
+        let round = app.world.resource::<Round>();
+        assert_eq!(round.loser, Some(0));
+        // The loser plays O now and starts the next round
+        let seating = app.world.resource::<Seating>();
+        assert_eq!(first_player(app.world.resource::<StartSettings>(), round, seating), CellState::O);
+
+        app.world.send_event(GameOverEvent { winner: CellState::None, winning_positions: None });
+        app.update();
+        assert_eq!(app.world.resource::<MatchScore>().draws, 1);
+        assert_eq!(app.world.resource::<Round>().loser, None);
+    }
+
+    #[test]
+    fn swap_is_only_offered_once_after_the_opening_move() {
+        let mut app = app();
+        app.world.insert_resource(State(GameState::XTurn));
+        app.world.spawn(Placement { player: CellState::X, order: 0 });
+
+        app.world.send_event(SwapSidesEvent);
+        app.update();
+        assert_eq!(app.world.resource::<Seating>().marks, [CellState::X, CellState::O]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::data::*;
+use super::{spawn_mark_sprite, HumanController};
+
+const FEEDBACK_TIME: Duration = Duration::from_millis(2000);
+
+// Profile both sides play under, a profile playing itself is neither rated nor earns achievements
+const TUTORIAL_PROFILE: &str = "Tutorial";
+
+// Plays the cells the lesson allows when the local player clicks them, O is given none
+pub struct TutorialController {
+    allowed: Vec<CellPosition>,
+}
+
+impl Controller for TutorialController {
+    fn is_human(&self) -> bool {
+        true
+    }
+
+    fn profile(&self) -> Option<String> {
+        Some(TUTORIAL_PROFILE.to_string())
+    }
+
+    fn poll_move(&mut self, request: &MoveRequest) -> Option<Move> {
+        request.local_move.filter(|(pos, _)| self.allowed.contains(pos))
+    }
+}
+
+fn start_step(tutorial: &mut Tutorial, step: Option<usize>, new_game_evt_wtr: &mut EventWriter<NewGameEvent>) {
+    tutorial.step = step;
+    tutorial.loading = step.is_some();
+    tutorial.board_cleared = false;
+    tutorial.feedback = None;
+    tutorial.blocked_click = false;
+    new_game_evt_wtr.send(NewGameEvent);
+}
+
+fn is_tutorial_rules(rules: &Rules) -> bool {
+    rules.variant == Variant::Classic && !rules.misere && !rules.wild
+}
+
+// Hands the sides back to whoever played them before the tutorial
+fn restore_players(tutorial: &mut Tutorial, players: &mut Players) {
+    if let Some(previous_players) = tutorial.previous_players.take() {
+        *players = previous_players;
+    }
+}
+
+pub fn toggle_tutorial(
+    mut toggle_tutorial_evt_rdr: EventReader<ToggleTutorialEvent>,
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut tutorial: ResMut<Tutorial>,
+    mut players: ResMut<Players>,
+    mut rules: ResMut<Rules>,
+) {
+    for _ in toggle_tutorial_evt_rdr.iter() {
+        if tutorial.step.is_some() {
+            restore_players(&mut tutorial, &mut players);
+            start_step(&mut tutorial, None, &mut new_game_evt_wtr);
+        } else {
+            if !is_tutorial_rules(&rules) {
+                *rules = Rules::default();
+            }
+            // Nobody plays until the first lesson is loaded
+            tutorial.previous_players = Some(std::mem::replace(&mut *players, Players {
+                x: Box::new(HumanController),
+                o: Box::new(HumanController),
+            }));
+            start_step(&mut tutorial, Some(0), &mut new_game_evt_wtr);
+        }
+    }
+}
+
+// Changing the rules from the keyboard leaves the tutorial
+pub fn stop_tutorial(
+    mut tutorial: ResMut<Tutorial>,
+    mut players: ResMut<Players>,
+    rules: Res<Rules>,
+) {
+    if tutorial.step.is_none() || !rules.is_changed() || is_tutorial_rules(&rules) { return; }
+
+    restore_players(&mut tutorial, &mut players);
+    *tutorial = Tutorial::default();
+}
+
+// Runs after reset_game and clear_board, so the lesson's marks go on the cleared board
+pub fn load_tutorial_step(
+    mut commands: Commands,
+    mut board_cleared_evt_rdr: EventReader<BoardClearedEvent>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut tutorial: ResMut<Tutorial>,
+    mut players: ResMut<Players>,
+    mut cell_qry: Query<&mut CellState>,
+    rules: Res<Rules>,
+    board: Res<Board>,
+    tex_atlas_handle: Res<TextureAtlasHandle>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+) {
+    let board_cleared = board_cleared_evt_rdr.iter().count() > 0;
+    let Some(step) = tutorial.step.filter(|_| tutorial.loading) else { return; };
+    if board_cleared {
+        tutorial.board_cleared = true;
+    }
+    if !tutorial.board_cleared { return; }
+    // A board rebuilt for the classic rules this frame has no cells until its commands are applied
+    if board.0.values().any(|ent| !cell_qry.contains(*ent)) { return; }
+    let lesson = &TUTORIAL[step];
+
+    let marks = lesson.x.iter().map(|cell| (*cell, CellState::X))
+        .chain(lesson.o.iter().map(|cell| (*cell, CellState::O)));
+    for (order, (cell, state)) in marks.enumerate() {
+        let pos = tutorial_cell(cell);
+        let Some(ent) = board.0.get(&pos) else { continue; };
+        let Ok(mut cell_state) = cell_qry.get_mut(*ent) else { continue; };
+        *cell_state = state;
+        let sprite_ent = spawn_mark_sprite(&mut commands, &rules, &tex_atlas_handle, &tex_atlas_indices, state, pos);
+        commands.entity(*ent)
+            .insert(Placement { player: state, order: order as u32 })
+            .add_child(sprite_ent);
+    }
+
+    let taken: Vec<_> = lesson.x.iter().chain(lesson.o.iter()).map(|cell| tutorial_cell(*cell)).collect();
+    tutorial.allowed = match lesson.allowed {
+        Some(allowed) => allowed.iter().map(|cell| tutorial_cell(*cell)).collect(),
+        None => board.0.keys().copied().filter(|pos| !taken.contains(pos)).collect(),
+    };
+    players.x = Box::new(TutorialController { allowed: tutorial.allowed.clone() });
+    players.o = Box::new(TutorialController { allowed: Vec::new() });
+    tutorial.loading = false;
+    next_game_state.set(GameState::XTurn);
+}
+
+pub fn check_tutorial_move(
+    mut local_move_evt_rdr: EventReader<LocalMoveEvent>,
+    mut cell_picked_evt_rdr: EventReader<CellPickedEvent>,
+    mut tutorial: ResMut<Tutorial>,
+    cell_qry: Query<&CellPosition>,
+) {
+    // Moves made while a lesson is loading or being wrapped up are left alone
+    let step = tutorial.step.filter(|_| !tutorial.loading && tutorial.feedback.is_none());
+
+    for evt in local_move_evt_rdr.iter() {
+        if step.is_none() { continue; }
+        let Ok(pos) = cell_qry.get(evt.entity) else { continue; };
+        tutorial.blocked_click = !tutorial.allowed.contains(pos);
+    }
+    for evt in cell_picked_evt_rdr.iter() {
+        let Some(step) = step else { continue; };
+        if evt.player != CellState::X { continue; }
+        let passed = TUTORIAL[step].intended.iter().any(|cell| tutorial_cell(*cell) == evt.position);
+        tutorial.feedback = Some((passed, Timer::new(FEEDBACK_TIME, TimerMode::Once)));
+        tutorial.blocked_click = false;
+    }
+}
+
+// Moves on once the feedback has been read, to the next lesson or back to the same one.
+// The last lesson hands the board back to whoever played before the tutorial.
+pub fn advance_tutorial(
+    mut new_game_evt_wtr: EventWriter<NewGameEvent>,
+    mut tutorial: ResMut<Tutorial>,
+    mut players: ResMut<Players>,
+    time: Res<Time>,
+) {
+    let (Some(step), Some((passed, timer))) = (tutorial.step, tutorial.feedback.as_mut()) else { return; };
+    timer.tick(time.delta());
+    if !timer.finished() { return; }
+
+    let next = if *passed { step + 1 } else { step };
+    if next < TUTORIAL.len() {
+        start_step(&mut tutorial, Some(next), &mut new_game_evt_wtr);
+    } else {
+        info!("tutorial complete");
+        restore_players(&mut tutorial, &mut players);
+        start_step(&mut tutorial, None, &mut new_game_evt_wtr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestController;
+
+    impl Controller for TestController {
+        fn profile(&self) -> Option<String> {
+            Some("test".to_string())
+        }
+
+        fn poll_move(&mut self, _: &MoveRequest) -> Option<Move> {
+            None
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app
+            .add_event::<ToggleTutorialEvent>()
+            .add_event::<NewGameEvent>()
+            .insert_resource(Players {
+                x: Box::new(HumanController),
+                o: Box::new(TestController),
+            })
+            .insert_resource(Rules { variant: Variant::Gravity, ..Rules::default() })
+            .init_resource::<Tutorial>()
+            .add_systems((toggle_tutorial, stop_tutorial.after(toggle_tutorial)));
+        app
+    }
+
+    fn o_profile(app: &App) -> Option<String> {
+        app.world.resource::<Players>().o.profile()
+    }
+
+    #[test]
+    fn leaving_the_tutorial_restores_the_players() {
+        let mut app = app();
+        app.world.send_event(ToggleTutorialEvent);
+        app.update();
+        assert_eq!(app.world.resource::<Tutorial>().step, Some(0));
+        assert_eq!(app.world.resource::<Rules>().variant, Variant::Classic);
+        assert_eq!(o_profile(&app), None);
+
+        app.world.send_event(ToggleTutorialEvent);
+        app.update();
+        assert_eq!(app.world.resource::<Tutorial>().step, None);
+        assert_eq!(o_profile(&app).as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn changing_the_rules_restores_the_players() {
+        let mut app = app();
+        app.world.send_event(ToggleTutorialEvent);
+        app.update();
+
+        app.world.resource_mut::<Rules>().misere = true;
+        app.update();
+        assert_eq!(app.world.resource::<Tutorial>().step, None);
+        assert_eq!(o_profile(&app).as_deref(), Some("test"));
+    }
+}
@@ -5,17 +5,12 @@ use bevy_mod_picking::{
 };
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
-mod data;
-use data::*;
-
-mod input;
-use input::InputPlugin;
-
-mod logic;
-use logic::LogicPlugin;
-
-mod ui;
-use ui::UiPlugin;
+use tictactoe::{
+    data::*,
+    input::InputPlugin,
+    logic::LogicPlugin,
+    ui::UiPlugin,
+};
 
 fn main() {
     let params = Params {
@@ -44,6 +39,10 @@ fn main() {
                 ..default()
             })
             .set(ImagePlugin::default_nearest())
+            .set(AssetPlugin {
+                watch_for_changes: true,
+                ..default()
+            })
         )
         .add_plugins(DefaultPickingPlugins)
         .add_plugin(LogicPlugin)
@@ -0,0 +1,144 @@
+// Rotations and reflections of a square board (the dihedral group D4). They act on centered
+// coordinates, so the center cell stays put and every symmetry is a sign flip and/or a swap
+// of row and column.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Symmetry {
+    Identity,
+    // Clockwise as seen on screen, rows grow downwards
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    // Mirrors left and right
+    FlipHorizontal,
+    // Mirrors top and bottom
+    FlipVertical,
+    // Mirrors along the diagonal through the top left corner
+    Transpose,
+    // Mirrors along the diagonal through the top right corner
+    AntiTranspose,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::FlipHorizontal,
+        Symmetry::FlipVertical,
+        Symmetry::Transpose,
+        Symmetry::AntiTranspose,
+    ];
+
+    pub fn apply(&self, (row, col): (i32, i32)) -> (i32, i32) {
+        match self {
+            Symmetry::Identity => (row, col),
+            Symmetry::Rotate90 => (col, -row),
+            Symmetry::Rotate180 => (-row, -col),
+            Symmetry::Rotate270 => (-col, row),
+            Symmetry::FlipHorizontal => (row, -col),
+            Symmetry::FlipVertical => (-row, col),
+            Symmetry::Transpose => (col, row),
+            Symmetry::AntiTranspose => (-col, -row),
+        }
+    }
+
+    // Maps a move found on a transformed board back onto the original one
+    pub fn inverse(&self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            // Everything else undoes itself
+            _ => *self,
+        }
+    }
+
+    // Cell index of a row by row indexed board with `size` cells per side
+    pub fn apply_index(&self, index: usize, size: usize) -> usize {
+        // Even sized boards have no center cell, doubling the coordinates keeps them whole
+        let (size, last) = (size as i32, size as i32 - 1);
+        let (row, col) = (index as i32 / size, index as i32 % size);
+        let (row, col) = self.apply((2 * row - last, 2 * col - last));
+        ((row + last) / 2 * size + (col + last) / 2) as usize
+    }
+}
+
+// Smallest of the forms a board takes under the symmetries, and the symmetry giving it.
+// Boards that are rotations or reflections of each other share it, so it can key tables
+// and deduplicate positions; moves found on it map back through the symmetry's inverse.
+pub fn canonical_form<T: Ord>(form: impl Fn(Symmetry) -> T) -> (T, Symmetry) {
+    Symmetry::ALL.iter()
+        .map(|symmetry| (form(*symmetry), *symmetry))
+        .min()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_undoes_apply() {
+        for symmetry in Symmetry::ALL {
+            for row in -2..=2 {
+                for col in -2..=2 {
+                    assert_eq!(symmetry.inverse().apply(symmetry.apply((row, col))), (row, col), "{:?}", symmetry);
+                    assert_eq!(symmetry.apply(symmetry.inverse().apply((row, col))), (row, col), "{:?}", symmetry);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn apply_matches_the_board_on_screen() {
+        // Top left corner of a 3x3 board
+        let corner = (-1, -1);
+        assert_eq!(Symmetry::Rotate90.apply(corner), (-1, 1));
+        assert_eq!(Symmetry::Rotate180.apply(corner), (1, 1));
+        assert_eq!(Symmetry::Rotate270.apply(corner), (1, -1));
+        assert_eq!(Symmetry::FlipHorizontal.apply(corner), (-1, 1));
+        assert_eq!(Symmetry::FlipVertical.apply(corner), (1, -1));
+        assert_eq!(Symmetry::Transpose.apply(corner), (-1, -1));
+        assert_eq!(Symmetry::AntiTranspose.apply(corner), (1, 1));
+        for symmetry in Symmetry::ALL {
+            assert_eq!(symmetry.apply((0, 0)), (0, 0));
+        }
+    }
+
+    #[test]
+    fn canonical_form_is_shared_by_symmetric_boards() {
+        // Cells of a 3x3 board holding a mark, row by row
+        let board = [true, true, false, false, false, false, false, false, true];
+        let form = |board: [bool; 9], symmetry: Symmetry| {
+            let mut transformed = [false; 9];
+            for (index, cell) in board.iter().enumerate() {
+                transformed[symmetry.apply_index(index, 3)] = *cell;
+            }
+            transformed
+        };
+
+        let (canonical, symmetry) = canonical_form(|symmetry| form(board, symmetry));
+        assert_eq!(form(board, symmetry), canonical);
+        for other in Symmetry::ALL {
+            let other_board = form(board, other);
+            let (other_canonical, _) = canonical_form(|symmetry| form(other_board, symmetry));
+            assert_eq!(other_canonical, canonical);
+        }
+    }
+
+    #[test]
+    fn apply_index_matches_apply() {
+        for size in [3, 4] {
+            // Doubled centered coordinates, like apply_index uses
+            let last = size as i32 - 1;
+            let coords = |index: usize| (2 * (index / size) as i32 - last, 2 * (index % size) as i32 - last);
+            for symmetry in Symmetry::ALL {
+                for index in 0..size * size {
+                    assert_eq!(coords(symmetry.apply_index(index, size)), symmetry.apply(coords(index)), "{:?} {}", symmetry, index);
+                    assert_eq!(symmetry.inverse().apply_index(symmetry.apply_index(index, size), size), index);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+// Solved values of every reachable classic 3x3 position. Positions that are rotations or
+// reflections of each other share one entry, keyed by the smallest of their codes.
+// The tablebase binary builds and verifies the table.
+
+use std::collections::HashMap;
+
+use crate::symmetry::{canonical_form, Symmetry};
+
+// Cells are indexed row by row and hold one of these
+pub const EMPTY: u8 = 0;
+pub const X: u8 = 1;
+pub const O: u8 = 2;
+
+pub type Cells = [u8; 9];
+
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2], [3, 4, 5], [6, 7, 8],
+    [0, 3, 6], [1, 4, 7], [2, 5, 8],
+    [0, 4, 8], [2, 4, 6],
+];
+
+const MAGIC: &[u8; 4] = b"TTTB";
+const VERSION: u8 = 1;
+const RECORD_LEN: usize = 7;
+
+// Scores use the same scale as the search in logic::ai: a line completed by the n-th move
+// from now (counting from 0) scores 10 - n for the side that completes it in normal play
+// and n - 10 in misère play, 0 is a draw
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Entry {
+    pub score: i8,
+    // Bit i is set when moving on cell i reaches the score
+    pub best_moves: u16,
+}
+
+#[derive(Default)]
+pub struct Tablebase {
+    entries: HashMap<u32, Entry>,
+}
+
+pub fn opponent(mark: u8) -> u8 {
+    if mark == X { O } else { X }
+}
+
+pub fn has_line(cells: &Cells, mark: u8) -> bool {
+    LINES.iter().any(|line| line.iter().all(|index| cells[*index] == mark))
+}
+
+// Either side may start, so the side to move is only implied by the marks when they differ
+pub fn is_reachable(cells: &Cells, to_move: u8) -> bool {
+    let xs = cells.iter().filter(|cell| **cell == X).count();
+    let os = cells.iter().filter(|cell| **cell == O).count();
+    let turn_ok = match xs as i32 - os as i32 {
+        0 => true,
+        1 => to_move == O,
+        -1 => to_move == X,
+        _ => false,
+    };
+    turn_ok && xs + os < 9 && !has_line(cells, X) && !has_line(cells, O)
+}
+
+fn encode(cells: &Cells, symmetry: Symmetry) -> u32 {
+    let mut transformed = [EMPTY; 9];
+    for index in 0..9 {
+        transformed[symmetry.apply_index(index, 3)] = cells[index];
+    }
+    transformed.iter().rev().fold(0, |code, cell| code * 3 + *cell as u32)
+}
+
+fn key(code: u32, to_move: u8, misere: bool) -> u32 {
+    code | (to_move as u32 - 1) << 15 | (misere as u32) << 16
+}
+
+fn canonical(cells: &Cells) -> (u32, Symmetry) {
+    canonical_form(|symmetry| encode(cells, symmetry))
+}
+
+fn decode(mut code: u32) -> Cells {
+    let mut cells = [EMPTY; 9];
+    for cell in cells.iter_mut() {
+        *cell = (code % 3) as u8;
+        code /= 3;
+    }
+    cells
+}
+
+// A line further away is worth one point less to the side that reaches it
+fn shrink(score: i8) -> i8 {
+    score - score.signum()
+}
+
+// Score of `to_move` playing on `index`, given the score of the position it leads to
+fn move_score(cells: &Cells, index: usize, to_move: u8, misere: bool, reply_score: impl FnOnce(&Cells) -> i8) -> i8 {
+    let mut after = *cells;
+    after[index] = to_move;
+    if has_line(&after, to_move) {
+        if misere { -10 } else { 10 }
+    } else if after.iter().all(|cell| *cell != EMPTY) {
+        0
+    } else {
+        -shrink(reply_score(&after))
+    }
+}
+
+fn best_of(cells: &Cells, mut score_of: impl FnMut(usize) -> i8) -> Entry {
+    let mut entry = Entry { score: i8::MIN, best_moves: 0 };
+    for index in (0..9).filter(|index| cells[*index] == EMPTY) {
+        let score = score_of(index);
+        if score > entry.score {
+            entry = Entry { score, best_moves: 0 };
+        }
+        if score == entry.score {
+            entry.best_moves |= 1 << index;
+        }
+    }
+    entry
+}
+
+impl Tablebase {
+    // Solves every reachable position for both starting sides, in normal and misère play
+    pub fn build() -> Self {
+        let mut tablebase = Self::default();
+        for misere in [false, true] {
+            for to_move in [X, O] {
+                tablebase.solve(&[EMPTY; 9], to_move, misere);
+            }
+        }
+        tablebase
+    }
+
+    fn solve(&mut self, cells: &Cells, to_move: u8, misere: bool) -> Entry {
+        let (code, symmetry) = canonical(cells);
+        let key = key(code, to_move, misere);
+        if let Some(entry) = self.entries.get(&key) {
+            return unmap(*entry, symmetry);
+        }
+
+        let entry = best_of(cells, |index| {
+            move_score(cells, index, to_move, misere, |after| self.solve(after, opponent(to_move), misere).score)
+        });
+        self.entries.insert(key, map(entry, symmetry));
+        entry
+    }
+
+    // O(1) lookup, None when the position can't come up in a game
+    pub fn get(&self, cells: &Cells, to_move: u8, misere: bool) -> Option<Entry> {
+        if !is_reachable(cells, to_move) { return None; }
+        let (code, symmetry) = canonical(cells);
+        self.entries.get(&key(code, to_move, misere)).map(|entry| unmap(*entry, symmetry))
+    }
+
+    // Score of every empty cell, the analysis overlay shows all of them
+    pub fn move_scores(&self, cells: &Cells, to_move: u8, misere: bool) -> Option<[Option<i8>; 9]> {
+        if !is_reachable(cells, to_move) { return None; }
+        let mut scores = [None; 9];
+        for index in (0..9).filter(|index| cells[*index] == EMPTY) {
+            let reply_score = |after: &Cells| {
+                self.get(after, opponent(to_move), misere).map_or(0, |entry| entry.score)
+            };
+            scores[index] = Some(move_score(cells, index, to_move, misere, reply_score));
+        }
+        Some(scores)
+    }
+
+    // Canonical entries, sorted so the same table always gives the same file
+    pub fn entries(&self) -> Vec<(Cells, u8, bool, Entry)> {
+        let mut keys: Vec<_> = self.entries.keys().copied().collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|key| (decode(key & 0x7fff), (key >> 15 & 1) as u8 + 1, key >> 16 & 1 == 1, self.entries[&key]))
+            .collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut keys: Vec<_> = self.entries.keys().copied().collect();
+        keys.sort();
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend((keys.len() as u32).to_le_bytes());
+        for key in keys {
+            let entry = self.entries[&key];
+            bytes.extend(key.to_le_bytes());
+            bytes.push(entry.score as u8);
+            bytes.extend(entry.best_moves.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 9 || &bytes[..4] != MAGIC {
+            return Err("not a tablebase file".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("tablebase version {} is not supported", bytes[4]));
+        }
+        let count = u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize;
+        let records = &bytes[9..];
+        if records.len() != count * RECORD_LEN {
+            return Err(format!("expected {} entries, found {} bytes", count, records.len()));
+        }
+
+        let entries = records.chunks_exact(RECORD_LEN)
+            .map(|record| {
+                let key = u32::from_le_bytes(record[..4].try_into().unwrap());
+                let entry = Entry {
+                    score: record[4] as i8,
+                    best_moves: u16::from_le_bytes(record[5..7].try_into().unwrap()),
+                };
+                (key, entry)
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+}
+
+// Best moves are stored as seen on the canonical board
+fn map(entry: Entry, symmetry: Symmetry) -> Entry {
+    let mut best_moves = 0;
+    for index in (0..9).filter(|index| entry.best_moves & 1 << index != 0) {
+        best_moves |= 1 << symmetry.apply_index(index, 3);
+    }
+    Entry { best_moves, ..entry }
+}
+
+fn unmap(entry: Entry, symmetry: Symmetry) -> Entry {
+    map(entry, symmetry.inverse())
+}
+
+// Plain minimax without the table or symmetries, used to check the table
+pub fn brute_force(cells: &Cells, to_move: u8, misere: bool) -> Entry {
+    best_of(cells, |index| {
+        move_score(cells, index, to_move, misere, |after| brute_force(after, opponent(to_move), misere).score)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_agrees_with_brute_force() {
+        let tablebase = Tablebase::build();
+        for code in 0..3u32.pow(9) {
+            let cells = decode(code);
+            for to_move in [X, O] {
+                for misere in [false, true] {
+                    if !is_reachable(&cells, to_move) {
+                        assert_eq!(tablebase.get(&cells, to_move, misere), None);
+                        assert_eq!(tablebase.move_scores(&cells, to_move, misere), None);
+                        continue;
+                    }
+
+                    assert_eq!(tablebase.get(&cells, to_move, misere), Some(brute_force(&cells, to_move, misere)));
+                    let mut expected = [None; 9];
+                    for index in (0..9).filter(|index| cells[*index] == EMPTY) {
+                        expected[index] = Some(move_score(&cells, index, to_move, misere, |after| {
+                            brute_force(after, opponent(to_move), misere).score
+                        }));
+                    }
+                    assert_eq!(tablebase.move_scores(&cells, to_move, misere), Some(expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn embedded_table_is_up_to_date() {
+        assert!(include_bytes!("../assets/tablebase.bin")[..] == Tablebase::build().to_bytes()[..]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let tablebase = Tablebase::build();
+        let read = Tablebase::from_bytes(&tablebase.to_bytes()).unwrap();
+        assert_eq!(read.entries(), tablebase.entries());
+        assert!(Tablebase::from_bytes(b"TTTB").is_err());
+    }
+}
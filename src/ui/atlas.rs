@@ -0,0 +1,159 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset, AssetPath},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use crate::data::*;
+
+// Order in which sprites are added to the atlas, this must match TextureAtlasIndices
+pub const SPRITE_NAMES: [&str; 10] = [
+    "bg",
+    "x",
+    "o",
+    "x_turn",
+    "o_turn",
+    "game_over_popup",
+    "o_text",
+    "o_text_bg",
+    "play_btn",
+    "quit_btn",
+];
+
+#[derive(Default)]
+pub struct AtlasManifestLoader;
+
+impl AssetLoader for AtlasManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let file: AtlasManifestFile = ron::de::from_bytes(bytes)?;
+            validate_regions(&file)?;
+
+            let image_path = AssetPath::new(file.image.into(), None);
+            let image = load_context.get_handle(image_path.get_id());
+            load_context.set_default_asset(
+                LoadedAsset::new(AtlasManifest {
+                    image,
+                    regions: file.regions,
+                })
+                .with_dependency(image_path)
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["atlas.ron"]
+    }
+}
+
+fn validate_regions(file: &AtlasManifestFile) -> Result<(), AtlasError> {
+    for name in SPRITE_NAMES {
+        if !file.regions.contains_key(name) {
+            return Err(AtlasError::MissingRegion(name.to_string()));
+        }
+    }
+
+    let mut regions: Vec<_> = file.regions.iter().collect();
+    regions.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, region) in regions.iter() {
+        if region.min.0 >= region.max.0 || region.min.1 >= region.max.1 {
+            return Err(AtlasError::EmptyRegion(name.to_string()));
+        }
+        if let Some(parent) = &region.part_of {
+            let parent_region = file.regions.get(parent).ok_or_else(|| AtlasError::UnknownParent {
+                region: name.to_string(),
+                parent: parent.clone(),
+            })?;
+            if !parent_region.contains(region) {
+                return Err(AtlasError::NotInParent {
+                    region: name.to_string(),
+                    parent: parent.clone(),
+                });
+            }
+        }
+    }
+
+    for (i, (name, region)) in regions.iter().enumerate() {
+        for (other_name, other) in regions.iter().skip(i + 1) {
+            let related = region.part_of.as_ref() == Some(*other_name)
+                || other.part_of.as_ref() == Some(*name);
+            if !related && region.overlaps(other) {
+                return Err(AtlasError::Overlapping(name.to_string(), other_name.to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn build_texture_atlas(
+    manifest: &AtlasManifest,
+    image: &Image,
+) -> Result<TextureAtlas, AtlasError> {
+    let size = image.size();
+    let image_size = (size.x as u32, size.y as u32);
+
+    for (name, region) in manifest.regions.iter() {
+        if region.max.0 > image_size.0 || region.max.1 > image_size.1 {
+            return Err(AtlasError::OutOfBounds {
+                region: name.clone(),
+                image_size,
+            });
+        }
+    }
+
+    let mut tex_atlas = TextureAtlas::new_empty(manifest.image.clone(), size);
+    for name in SPRITE_NAMES {
+        tex_atlas.add_texture(manifest.regions[name].rect());
+    }
+    Ok(tex_atlas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> AtlasManifestFile {
+        ron::de::from_str(include_str!("../../assets/atlas.atlas.ron")).unwrap()
+    }
+
+    fn region(min: (u32, u32), max: (u32, u32), part_of: Option<&str>) -> AtlasRegion {
+        AtlasRegion { min, max, part_of: part_of.map(|parent| parent.to_string()) }
+    }
+
+    #[test]
+    fn shipped_atlases_are_valid() {
+        assert!(validate_regions(&manifest()).is_ok());
+        let high_contrast = ron::de::from_str(include_str!("../../assets/atlas_high_contrast.atlas.ron")).unwrap();
+        assert!(validate_regions(&high_contrast).is_ok());
+    }
+
+    #[test]
+    fn rejects_broken_regions() {
+        let mut file = manifest();
+        file.regions.remove("x");
+        assert!(matches!(validate_regions(&file), Err(AtlasError::MissingRegion(name)) if name == "x"));
+
+        let mut file = manifest();
+        file.regions.insert("x".to_string(), region((192, 97), (192, 113), None));
+        assert!(matches!(validate_regions(&file), Err(AtlasError::EmptyRegion(name)) if name == "x"));
+
+        let mut file = manifest();
+        file.regions.insert("o".to_string(), region((200, 97), (216, 113), None));
+        assert!(matches!(validate_regions(&file), Err(AtlasError::Overlapping(..))));
+
+        let mut file = manifest();
+        file.regions.insert("o_text".to_string(), region((192, 89), (196, 94), Some("missing")));
+        assert!(matches!(validate_regions(&file), Err(AtlasError::UnknownParent { .. })));
+
+        let mut file = manifest();
+        file.regions.insert("o_text".to_string(), region((192, 89), (196, 94), Some("x")));
+        assert!(matches!(validate_regions(&file), Err(AtlasError::NotInParent { .. })));
+    }
+}
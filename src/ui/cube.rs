@@ -0,0 +1,174 @@
+use bevy::{prelude::*, core_pipeline::clear_color::ClearColorConfig, utils::{HashMap, HashSet}};
+use bevy_mod_picking::{PickableBundle, PickingEvent, HoverEvent};
+
+use crate::data::*;
+
+// Far away from the 2D scene so neither camera's picking rays reach the other's meshes
+pub const CUBE_ORIGIN: Vec3 = Vec3::new(5000., 0., 0.);
+
+pub fn init_cube_materials(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut unlit = |color: Color| materials.add(StandardMaterial {
+        base_color: color,
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    commands.insert_resource(CubeMaterials {
+        empty: unlit(Color::rgba(1., 1., 1., 0.15)),
+        // Colors come from the current theme, see apply_theme
+        hovered: unlit(Color::NONE),
+        winner: unlit(Color::NONE),
+        x: unlit(Color::rgb(0.9, 0.3, 0.3)),
+        o: unlit(Color::rgb(0.3, 0.5, 0.95)),
+    });
+}
+
+pub fn spawn_cube_board(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    cube_mats: &CubeMaterials,
+    variant: Variant,
+) {
+    let board_ent = commands.spawn(SpatialBundle {
+        transform: Transform::from_translation(CUBE_ORIGIN),
+        ..default()
+    })
+        .insert(BoardRoot)
+        .insert(Name::new("Cube Board"))
+        .id();
+
+    let mesh = meshes.add(Mesh::from(shape::UVSphere { radius: 0.2, ..default() }));
+    let center = |range: std::ops::RangeInclusive<i32>| (range.start() + range.end()) as f32 / 2.;
+    let (row_offset, col_offset, layer_offset) = (
+        center(variant.rows()),
+        center(variant.cols()),
+        center(variant.layers()),
+    );
+
+    let mut board = Board(HashMap::new());
+    for layer in variant.layers() {
+        for row in variant.rows() {
+            for col in variant.cols() {
+                let cell_pos = CellPosition { row, col, layer };
+                let translation = Vec3::new(
+                    col as f32 - col_offset,
+                    -(row as f32 - row_offset),
+                    -(layer as f32 - layer_offset),
+                );
+                let cell_ent = commands.spawn((
+                    PbrBundle {
+                        mesh: mesh.clone(),
+                        material: cube_mats.empty.clone_weak(),
+                        transform: Transform::from_translation(translation),
+                        ..default()
+                    },
+                    PickableBundle::default(),
+                    CellState::None,
+                    cell_pos,
+                    CubeCell,
+                    Name::new("Cell"),
+                )).id();
+                commands.entity(board_ent).add_child(cell_ent);
+                board.0.insert(cell_pos, cell_ent);
+            }
+        }
+    }
+
+    commands.insert_resource(board);
+    commands.insert_resource(UltimateBoard::default());
+}
+
+// The 3D camera draws the cube underneath the 2D scene, which then must not clear it
+pub fn sync_cube_camera(
+    mut cube_camera_qry: Query<&mut Camera, With<OrbitCamera>>,
+    mut camera_2d_qry: Query<&mut Camera2d>,
+    rules: Res<Rules>,
+) {
+    if !rules.is_changed() { return; }
+
+    let is_cube = rules.variant.is_cube();
+    for mut camera in cube_camera_qry.iter_mut() {
+        camera.is_active = is_cube;
+    }
+    for mut camera_2d in camera_2d_qry.iter_mut() {
+        camera_2d.clear_color = if is_cube {
+            ClearColorConfig::None
+        } else {
+            ClearColorConfig::Default
+        };
+    }
+}
+
+pub fn update_orbit_camera(
+    mut orbit_qry: Query<(&OrbitCamera, &mut Transform), Changed<OrbitCamera>>,
+) {
+    for (orbit, mut transform) in orbit_qry.iter_mut() {
+        let rotation = Quat::from_euler(EulerRot::YXZ, orbit.yaw, -orbit.pitch, 0.);
+        *transform = Transform::from_translation(CUBE_ORIGIN + rotation * Vec3::Z * orbit.radius)
+            .looking_at(CUBE_ORIGIN, Vec3::Y);
+    }
+}
+
+pub fn update_cube_cells(
+    mut commands: Commands,
+    mut picking_evt_rdr: EventReader<PickingEvent>,
+    mut game_over_evt_rdr: EventReader<GameOverEvent>,
+    // The winning mark's own state change may only show up after the game over event
+    mut winners: Local<HashSet<Entity>>,
+    cube_mats: Res<CubeMaterials>,
+    board: Res<Board>,
+    game_state: Res<State<GameState>>,
+    cell_qry: Query<&CellState, With<CubeCell>>,
+    changed_cell_qry: Query<(Entity, &CellState), (With<CubeCell>, Changed<CellState>)>,
+    hinted_qry: Query<(), With<HintedCell>>,
+) {
+    for evt in game_over_evt_rdr.iter() {
+        let Some(winning_positions) = &evt.winning_positions else { continue; };
+        winners.clear();
+        winners.extend(winning_positions.iter()
+            .filter_map(|pos| board.0.get(pos))
+            .filter(|ent| cell_qry.contains(**ent)));
+        for ent in winners.iter() {
+            commands.entity(*ent).insert(cube_mats.winner.clone_weak());
+        }
+    }
+
+    for (ent, state) in changed_cell_qry.iter() {
+        if *state == CellState::None {
+            winners.remove(&ent);
+        }
+    }
+    let state_material = |ent: Entity, state: CellState| match state {
+        _ if winners.contains(&ent) => cube_mats.winner.clone_weak(),
+        CellState::X => cube_mats.x.clone_weak(),
+        CellState::O => cube_mats.o.clone_weak(),
+        CellState::None => cube_mats.empty.clone_weak(),
+    };
+
+    for (ent, state) in changed_cell_qry.iter() {
+        commands.entity(ent).insert(state_material(ent, *state));
+    }
+
+    for evt in picking_evt_rdr.iter() {
+        match evt {
+            PickingEvent::Hover(HoverEvent::JustEntered(ent)) => {
+                if game_state.0 == GameState::GameOver { continue; }
+                if let Ok(CellState::None) = cell_qry.get(*ent) {
+                    commands.entity(*ent).insert(cube_mats.hovered.clone_weak());
+                }
+            },
+            PickingEvent::Hover(HoverEvent::JustLeft(ent)) => {
+                // Leaving a hinted cell keeps the hint visible
+                if hinted_qry.contains(*ent) { continue; }
+                if let Ok(state) = cell_qry.get(*ent) {
+                    commands.entity(*ent).insert(state_material(*ent, *state));
+                }
+            },
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+
+use crate::data::*;
+use super::init::{cell_translation, spawn_slotted_cell, tile_size};
+
+// Grows an infinite board so every mark stays surrounded by empty cells
+pub fn extend_infinite_board(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut board: ResMut<Board>,
+    mat_handles: Res<MaterialHandles>,
+    params: Res<Params>,
+    rules: Res<Rules>,
+    board_root_qry: Query<Entity, With<BoardRoot>>,
+    changed_cell_qry: Query<(&CellState, &CellPosition), Changed<CellState>>,
+) {
+    if rules.variant != Variant::Infinite { return; }
+    let Ok(board_ent) = board_root_qry.get_single() else { return; };
+
+    let tile_size = tile_size(rules.variant, &params);
+    for (state, pos) in changed_cell_qry.iter() {
+        if *state == CellState::None { continue; }
+
+        for cell_pos in margin_cells(*pos, |cell_pos| board.0.contains_key(&cell_pos)) {
+            let transform = Transform::from_scale(Vec3::splat(tile_size * 1.12))
+                .with_translation(cell_translation(rules.variant, &params, cell_pos));
+            let cell_ent = spawn_slotted_cell(&mut commands, &mut meshes, &mat_handles, transform, cell_pos);
+            commands.entity(board_ent).add_child(cell_ent);
+            board.0.insert(cell_pos, cell_ent);
+        }
+    }
+}
+
+pub fn update_board_view(
+    mut view: ResMut<BoardView>,
+    mut board_root_qry: Query<&mut Transform, With<BoardRoot>>,
+    rules: Res<Rules>,
+) {
+    // Every variant starts out centered
+    if rules.is_changed() {
+        *view = BoardView::default();
+    }
+    if rules.variant != Variant::Infinite || !view.is_changed() { return; }
+
+    for mut transform in board_root_qry.iter_mut() {
+        transform.translation = view.offset.extend(transform.translation.z);
+        transform.scale = Vec3::splat(view.zoom);
+    }
+}
@@ -1,66 +1,179 @@
-use bevy::{prelude::*, utils::HashMap, sprite::{MaterialMesh2dBundle, Mesh2dHandle}};
+use bevy::{prelude::*, utils::HashMap, sprite::{Anchor, MaterialMesh2dBundle, Mesh2dHandle}, text::Text2dBounds};
 use bevy_mod_picking::{PickableBundle, PickingCameraBundle};
 
 use crate::data::*;
+use super::cube::spawn_cube_board;
 
 pub fn spawn_board(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mat_handles: Res<MaterialHandles>,
+    cube_mats: Res<CubeMaterials>,
     tex_atlas_handle: Res<TextureAtlasHandle>,
     tex_atlas_indices: Res<TextureAtlasIndices>,
     params: Res<Params>,
+    rules: Res<Rules>,
+    board_root_qry: Query<Entity, With<BoardRoot>>,
 ) {
+    // Also used to rebuild the board when the variant changes
+    for ent in board_root_qry.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+
+    if rules.variant.is_cube() {
+        spawn_cube_board(&mut commands, &mut meshes, &cube_mats, rules.variant);
+        return;
+    }
+
     let board_ent = commands.spawn(SpatialBundle {
         transform: Transform::from_translation(Vec3::new(0., 0., -100.)),
         ..default()
     })
+        .insert(BoardRoot)
         .insert(Name::new("Board"))
         .id();
         
-    let bg_ent = commands.spawn((
-        SpriteSheetBundle {
-            sprite: TextureAtlasSprite::new(tex_atlas_indices.bg),
-            texture_atlas: tex_atlas_handle.0.clone_weak(),
-            transform: Transform::from_scale(Vec3::splat(8.))
-                .with_translation(Vec3::new(0., 0., -100.)),
-            ..default()
-        },
-        Name::new("Background"),
-    )).id();
-    commands.entity(board_ent).add_child(bg_ent);
+    // The grid lines in the atlas only fit 3x3 boards
+    let (rows, cols) = rules.variant.size();
+    if (rows, cols) == (3, 3) {
+        let bg_ent = commands.spawn((
+            SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(tex_atlas_indices.bg),
+                texture_atlas: tex_atlas_handle.0.clone_weak(),
+                transform: Transform::from_scale(Vec3::splat(8.))
+                    .with_translation(Vec3::new(0., 0., -100.)),
+                ..default()
+            },
+            Name::new("Background"),
+        )).id();
+        commands.entity(board_ent).add_child(bg_ent);
+    }
     
     let mut board = Board(HashMap::new());
+    let mut ultimate = UltimateBoard::default();
+    let tile_size = tile_size(rules.variant, &params);
     
-    for row in -1..=1 {
-        for col in -1..=1 {
-            let gap_multiplier = 1.18;
-            let transform = Transform::from_scale(Vec3::splat(params.tile_size * 1.12))
-                .with_translation(Vec3::new(
-                    col as f32 * params.tile_size * gap_multiplier,
-                    -(row as f32 * params.tile_size * gap_multiplier + 52.),
-                    0.,
-                ));
-            let cell_pos = CellPosition { row, col };
-            let cell_ent = commands.spawn((
-                MaterialMesh2dBundle {
-                    mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                    transform,
-                    material: mat_handles.transparent.clone_weak(),
-                    ..default()
+    for row in rules.variant.rows() {
+        for col in rules.variant.cols() {
+            let cell_pos = CellPosition { row, col, layer: 0 };
+            let translation = cell_translation(rules.variant, &params, cell_pos);
+
+            match rules.variant {
+                Variant::Classic | Variant::Disappearing => {
+                    let transform = Transform::from_scale(Vec3::splat(tile_size * 1.12))
+                        .with_translation(translation);
+                    let cell_ent = spawn_cell(&mut commands, &mut meshes, &mat_handles, transform, cell_pos);
+                    commands.entity(board_ent).add_child(cell_ent);
+                    board.0.insert(cell_pos, cell_ent);
+                },
+                Variant::Gravity | Variant::Infinite => {
+                    let transform = Transform::from_scale(Vec3::splat(tile_size * 1.12))
+                        .with_translation(translation);
+                    let cell_ent = spawn_slotted_cell(&mut commands, &mut meshes, &mat_handles, transform, cell_pos);
+                    commands.entity(board_ent).add_child(cell_ent);
+                    board.0.insert(cell_pos, cell_ent);
                 },
-                PickableBundle::default(),
-                CellState::None,
-                cell_pos,
-                Name::new("Cell"),
-            )).id();
-            commands.entity(board_ent).add_child(cell_ent);
-            
-            board.0.insert(cell_pos, cell_ent);
+                Variant::Ultimate => {
+                    // Each cell of the big board holds a whole small board
+                    let sub_board_ent = commands.spawn(MaterialMesh2dBundle {
+                        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::splat(tile_size * 1.12)))).into(),
+                        transform: Transform::from_translation(translation),
+                        material: mat_handles.transparent.clone_weak(),
+                        ..default()
+                    })
+                        .insert(SubBoard(cell_pos))
+                        .insert(Name::new("Sub Board"))
+                        .id();
+                    commands.entity(board_ent).add_child(sub_board_ent);
+                    board.0.insert(cell_pos, sub_board_ent);
+
+                    let small_tile_size = tile_size / 3.3;
+                    let mut small_board = Board(HashMap::new());
+                    for small_row in -1..=1 {
+                        for small_col in -1..=1 {
+                            let transform = Transform::from_scale(Vec3::splat(small_tile_size * 1.12))
+                                .with_translation(Vec3::new(
+                                    small_col as f32 * small_tile_size * GAP_MULTIPLIER,
+                                    -(small_row as f32 * small_tile_size * GAP_MULTIPLIER),
+                                    0.1,
+                                ));
+                            let small_pos = CellPosition { row: small_row, col: small_col, layer: 0 };
+                            let cell_ent = spawn_cell(&mut commands, &mut meshes, &mat_handles, transform, small_pos);
+                            commands.entity(cell_ent).insert(SubBoard(cell_pos));
+                            commands.entity(sub_board_ent).add_child(cell_ent);
+                            small_board.0.insert(small_pos, cell_ent);
+                        }
+                    }
+                    ultimate.boards.insert(cell_pos, small_board);
+                },
+                // Already spawned by spawn_cube_board
+                Variant::Cube3 | Variant::Cube4 => (),
+            }
         }
     }
     
     commands.insert_resource(board);
+    commands.insert_resource(ultimate);
+}
+
+const GAP_MULTIPLIER: f32 = 1.18;
+
+// Bigger boards shrink their cells to take up the same space
+pub fn tile_size(variant: Variant, params: &Params) -> f32 {
+    let (rows, cols) = variant.size();
+    params.tile_size * 3. / rows.max(cols) as f32
+}
+
+pub fn cell_translation(variant: Variant, params: &Params, pos: CellPosition) -> Vec3 {
+    let tile_size = tile_size(variant, params);
+    let row_offset = (variant.rows().start() + variant.rows().end()) as f32 / 2.;
+    Vec3::new(
+        pos.col as f32 * tile_size * GAP_MULTIPLIER,
+        -((pos.row as f32 - row_offset) * tile_size * GAP_MULTIPLIER + 52.),
+        0.,
+    )
+}
+
+// Boards without grid lines draw a slot behind every cell
+pub fn spawn_slotted_cell(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    mat_handles: &MaterialHandles,
+    transform: Transform,
+    cell_pos: CellPosition,
+) -> Entity {
+    let cell_ent = spawn_cell(commands, meshes, mat_handles, transform, cell_pos);
+    let slot_ent = commands.spawn(MaterialMesh2dBundle {
+        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::splat(0.9)))).into(),
+        transform: Transform::from_translation(Vec3::new(0., 0., -0.1)),
+        material: mat_handles.bg.clone_weak(),
+        ..default()
+    })
+        .insert(Name::new("Slot"))
+        .id();
+    commands.entity(cell_ent).add_child(slot_ent);
+    cell_ent
+}
+
+fn spawn_cell(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    mat_handles: &MaterialHandles,
+    transform: Transform,
+    cell_pos: CellPosition,
+) -> Entity {
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform,
+            material: mat_handles.transparent.clone_weak(),
+            ..default()
+        },
+        PickableBundle::default(),
+        CellState::None,
+        cell_pos,
+        Name::new("Cell"),
+    )).id()
 }
 
 pub fn spawn_turn_text(
@@ -80,12 +193,197 @@ pub fn spawn_turn_text(
         .insert(Name::new("Turn Text"));
 }
 
+// Instructions of the current lesson, over the hint and score texts while the tutorial runs
+pub fn spawn_tutorial_tooltip(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mat_handles: Res<MaterialHandles>,
+    font_handle: Res<FontHandle>,
+) {
+    let text_ent = commands.spawn(Text2dBundle {
+        text: Text::from_section("", TextStyle {
+            font: font_handle.0.clone_weak(),
+            font_size: 22.,
+            color: Color::WHITE,
+        }),
+        text_2d_bounds: Text2dBounds { size: Vec2::new(460., 150.) },
+        transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
+        ..default()
+    })
+        .insert(TutorialText)
+        .insert(Name::new("Tutorial Text"))
+        .id();
+
+    commands.spawn(MaterialMesh2dBundle {
+        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::new(490., 160.)))).into(),
+        material: mat_handles.bg.clone_weak(),
+        transform: Transform::from_translation(Vec3::new(0., -360., -97.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(TutorialTooltip)
+        .insert(Name::new("Tutorial Tooltip"))
+        .add_child(text_ent);
+}
+
+// One line per side above the turn text, the profile's avatar followed by its name and rating
+pub fn spawn_profile_ui(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tex_atlas_handle: Res<TextureAtlasHandle>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+    mat_handles: Res<MaterialHandles>,
+    font_handle: Res<FontHandle>,
+) {
+    for (side, y) in [(CellState::X, 336.), (CellState::O, 308.)] {
+        commands.spawn(SpriteSheetBundle {
+            texture_atlas: tex_atlas_handle.0.clone_weak(),
+            sprite: TextureAtlasSprite::new(tex_atlas_indices.x),
+            transform: Transform::from_scale(Vec3::splat(1.4))
+                .with_translation(Vec3::new(-190., y, -99.)),
+            ..default()
+        })
+            .insert(ProfileAvatar(side))
+            .insert(Name::new("Profile Avatar"));
+
+        commands.spawn(Text2dBundle {
+            text: Text::from_section("", TextStyle {
+                font: font_handle.0.clone_weak(),
+                font_size: 22.,
+                color: Color::WHITE,
+            }),
+            text_anchor: Anchor::CenterLeft,
+            transform: Transform::from_translation(Vec3::new(-170., y, -99.)),
+            ..default()
+        })
+            .insert(ProfileText(side))
+            .insert(Name::new("Profile Text"));
+    }
+
+    let stats_text_ent = commands.spawn(Text2dBundle {
+        text: Text::from_section("", TextStyle {
+            font: font_handle.0.clone_weak(),
+            font_size: 22.,
+            color: Color::WHITE,
+        }),
+        transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
+        ..default()
+    })
+        .insert(ProfileStatsText)
+        .insert(Name::new("Profile Stats Text"))
+        .id();
+
+    commands.spawn(MaterialMesh2dBundle {
+        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::new(440., 440.)))).into(),
+        material: mat_handles.bg.clone_weak(),
+        transform: Transform::from_translation(Vec3::new(0., -20., -97.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(ProfileStatsPanel)
+        .insert(Name::new("Profile Stats Panel"))
+        .add_child(stats_text_ent);
+}
+
+pub fn spawn_mark_choice_indicator(
+    mut commands: Commands,
+    tex_atlas_handle: Res<TextureAtlasHandle>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+) {
+    commands.spawn(SpriteSheetBundle {
+        texture_atlas: tex_atlas_handle.0.clone_weak(),
+        sprite: TextureAtlasSprite::new(tex_atlas_indices.x),
+        transform: Transform::from_scale(Vec3::splat(0.03))
+            .with_translation(Vec3::new(-165., 270., -99.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(MarkChoiceIndicator)
+        .insert(Name::new("Mark Choice Indicator"));
+}
+
+pub fn spawn_swap_text(
+    mut commands: Commands,
+    font_handle: Res<FontHandle>,
+) {
+    commands.spawn(Text2dBundle {
+        text: Text::from_section("S: swap sides", TextStyle {
+            font: font_handle.0.clone_weak(),
+            font_size: 24.,
+            color: Color::WHITE,
+        }),
+        transform: Transform::from_translation(Vec3::new(0., 232., -99.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(SwapText)
+        .insert(Name::new("Swap Text"));
+}
+
+pub fn spawn_hint_ui(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    font_handle: Res<FontHandle>,
+) {
+    let text_style = |font_size: f32| TextStyle {
+        font: font_handle.0.clone_weak(),
+        font_size,
+        color: Color::WHITE,
+    };
+
+    commands.spawn(Text2dBundle {
+        text: Text::from_section("", text_style(28.)),
+        transform: Transform::from_translation(Vec3::new(0., -320., -99.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(HintText)
+        .insert(Name::new("Hint Text"));
+
+    commands.spawn(Text2dBundle {
+        text: Text::from_section("", text_style(24.)),
+        transform: Transform::from_translation(Vec3::new(0., -360., -99.)),
+        ..default()
+    })
+        .insert(ScoreText)
+        .insert(Name::new("Score Text"));
+
+    commands.spawn(Text2dBundle {
+        text: Text::from_section("HINT (H)", text_style(28.)),
+        transform: Transform::from_translation(Vec3::new(0., -405., -99.)),
+        ..default()
+    })
+        .insert(Mesh2dHandle(meshes.add(Mesh::from(shape::Quad::new(Vec2::new(130., 36.)))).into()))
+        .insert(HintBtn)
+        .insert(PickableBundle::default())
+        .insert(Name::new("Hint Button"));
+}
+
+pub fn spawn_clock_text(
+    mut commands: Commands,
+    font_handle: Res<FontHandle>,
+) {
+    commands.spawn(Text2dBundle {
+        text: Text::from_section("", TextStyle {
+            font: font_handle.0.clone_weak(),
+            font_size: 36.,
+            color: Color::WHITE,
+        }),
+        transform: Transform::from_translation(Vec3::new(170., 270., -99.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(ClockText)
+        .insert(Name::new("Clock Text"));
+}
+
 pub fn spawn_game_over_popup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     tex_atlas_handle: Res<TextureAtlasHandle>,
     tex_atlas_indices: Res<TextureAtlasIndices>,
     mat_handles: Res<MaterialHandles>,
+    font_handle: Res<FontHandle>,
 ) {
     let o_text_sprite_ent = commands.spawn(SpriteSheetBundle {
         texture_atlas: tex_atlas_handle.0.clone_weak(),
@@ -114,12 +412,48 @@ pub fn spawn_game_over_popup(
         .add_child(o_text_bg_sprite_ent)
         .id();
 
+    // Covers the "X WON!" line of the popup when the game is drawn
+    let draw_text_bg_ent = commands.spawn(SpriteSheetBundle {
+        texture_atlas: tex_atlas_handle.0.clone_weak(),
+        sprite: TextureAtlasSprite::new(tex_atlas_indices.o_text_bg),
+        transform: Transform::from_scale(Vec3::new(8., 1.2, 1.))
+            .with_translation(Vec3::new(0., 0., 1.)),
+        ..default()
+    })
+        .insert(Name::new("Draw Text Background"))
+        .id();
+
+    let draw_text_label_ent = commands.spawn(Text2dBundle {
+        text: Text::from_section("DRAW!", TextStyle {
+            font: font_handle.0.clone_weak(),
+            font_size: 40.,
+            color: Color::WHITE,
+        }),
+        transform: Transform::from_scale(Vec3::splat(1. / 8.))
+            .with_translation(Vec3::new(0., 0., 2.)),
+        ..default()
+    })
+        .insert(Name::new("Draw Text Label"))
+        .id();
+
+    let draw_text_ent = commands.spawn(SpatialBundle {
+        transform: Transform::from_translation(Vec3::new(0., 15.5, 1.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+        .insert(DrawText)
+        .insert(Name::new("Draw Text"))
+        .add_child(draw_text_bg_ent)
+        .add_child(draw_text_label_ent)
+        .id();
+
     let play_btn_ent = commands.spawn(SpriteSheetBundle {
         texture_atlas: tex_atlas_handle.0.clone_weak(),
         sprite: TextureAtlasSprite::new(tex_atlas_indices.play_btn),
         transform: Transform::from_translation(Vec3::new(0., 4.5, 1.)),
         ..default()
     })
+        .insert(Mesh2dHandle(meshes.add(Mesh::from(shape::Quad::new(Vec2::new(25., 7.)))).into()))
         .insert(PlayBtn)
         .insert(PickableBundle::default())
         .insert(Name::new("Play Button"))
@@ -131,7 +465,7 @@ pub fn spawn_game_over_popup(
         transform: Transform::from_translation(Vec3::new(0., -4.5, 1.)),
         ..default()
     })
-        .insert(Mesh2dHandle(meshes.add(Mesh::from(shape::Quad::default())).into()))
+        .insert(Mesh2dHandle(meshes.add(Mesh::from(shape::Quad::new(Vec2::new(25., 7.)))).into()))
         .insert(QuitBtn)
         .insert(PickableBundle::default())
         .insert(Name::new("Quit Button"))
@@ -148,6 +482,7 @@ pub fn spawn_game_over_popup(
         .insert(GameOverPopup::X)
         .insert(Name::new("Game Over Popup"))
         .add_child(o_text_ent)
+        .add_child(draw_text_ent)
         .add_child(play_btn_ent)
         .add_child(quit_btn_ent);
 }
@@ -155,68 +490,37 @@ pub fn spawn_game_over_popup(
 pub fn init_textures(
     mut commands: Commands,
     mut tex_atlases: ResMut<Assets<TextureAtlas>>,
-    asset_server: Res<AssetServer>,
 ) {
-    let tex_handle = asset_server.load("../assets/atlas.png");
-    let mut tex_atlas = TextureAtlas::new_empty(tex_handle, Vec2::new(248., 119.));
-
-    let bg = tex_atlas.add_texture(Rect {
-        min: Vec2::new(125., 3.),
-        max: Vec2::new(189., 116.),
-    });
-    let x = tex_atlas.add_texture(Rect {
-        min: Vec2::new(192., 97.),
-        max: Vec2::new(208., 113.),
-    });
-    let o = tex_atlas.add_texture(Rect {
-        min: Vec2::new(211., 97.),
-        max: Vec2::new(227., 113.),
-    });
-    let x_turn = tex_atlas.add_texture(Rect {
-        min: Vec2::new(192., 82.),
-        max: Vec2::new(218., 87.),
-    });
-    let o_turn = tex_atlas.add_texture(Rect {
-        min: Vec2::new(192., 89.),
-        max: Vec2::new(218., 94.),
-    });
-    let game_over_popup= tex_atlas.add_texture(Rect {
-        min: Vec2::new(191., 35.),
-        max: Vec2::new(245., 75.),
-    });
-    let o_text = tex_atlas.add_texture(Rect {
-        min: Vec2::new(192., 89.),
-        max: Vec2::new(196., 94.),
-    });
-    let o_text_bg = tex_atlas.add_texture(Rect {
-        min: Vec2::new(202., 37.),
-        max: Vec2::new(206., 42.)
-    });
-    let play_btn = tex_atlas.add_texture(Rect {
-        min: Vec2::new(191., 17.),
-        max: Vec2::new(216., 24.),
-    });
-    let quit_btn = tex_atlas.add_texture(Rect {
-        min: Vec2::new(191., 27.),
-        max: Vec2::new(216., 34.),
-    });
+    // The real regions are filled in by apply_theme once the current theme has loaded,
+    // until then every sprite is an empty region so indices stay valid
+    let mut tex_atlas = TextureAtlas::new_empty(Handle::default(), Vec2::ONE);
+    let mut placeholder = || tex_atlas.add_texture(Rect::default());
     commands.insert_resource(TextureAtlasIndices {
-        bg,
-        x,
-        o,
-        x_turn,
-        o_turn,
-        game_over_popup,
-        o_text,
-        o_text_bg,
-        play_btn,
-        quit_btn,
+        bg: placeholder(),
+        x: placeholder(),
+        o: placeholder(),
+        x_turn: placeholder(),
+        o_turn: placeholder(),
+        game_over_popup: placeholder(),
+        o_text: placeholder(),
+        o_text_bg: placeholder(),
+        play_btn: placeholder(),
+        quit_btn: placeholder(),
     });
 
     let tex_atlas_handle = tex_atlases.add(tex_atlas);
     commands.insert_resource(TextureAtlasHandle(tex_atlas_handle));
 }
 
+pub fn init_fonts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(FontHandle(asset_server.load("fonts/DejaVuSans-Bold.ttf")));
+    // Replaced by the current theme's text color, see apply_theme
+    commands.insert_resource(TextColor(Color::WHITE));
+}
+
 pub fn init_materials(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
@@ -226,18 +530,29 @@ pub fn init_materials(
         ..default()
     });
     
+    // Colors come from the current theme, see apply_theme
     let hovered = materials.add(ColorMaterial {
-        color: Color::hex("#6540537f").unwrap(),
+        color: Color::NONE,
         ..default()
     });
     
     let winner = materials.add(ColorMaterial {
-        color: Color::hex("#654053").unwrap(),
+        color: Color::NONE,
+        ..default()
+    });
+
+    let strike = materials.add(ColorMaterial {
+        color: Color::NONE,
         ..default()
     });
 
     let bg = materials.add(ColorMaterial {
-        color: Color::hex("#654053").unwrap(),
+        color: Color::NONE,
+        ..default()
+    });
+
+    let hint = materials.add(ColorMaterial {
+        color: Color::NONE,
         ..default()
     });
     
@@ -245,7 +560,9 @@ pub fn init_materials(
         transparent,
         hovered,
         winner,
+        strike,
         bg,
+        hint,
     });
 }
 
@@ -258,4 +575,17 @@ pub fn spawn_camera(
     })
         .insert(PickingCameraBundle::default())
         .insert(Name::new("Camera"));
+
+    // Renders cube boards before the 2D camera draws everything else on top, see sync_cube_camera
+    commands.spawn(Camera3dBundle {
+        camera: Camera {
+            order: -1,
+            is_active: false,
+            ..default()
+        },
+        ..default()
+    })
+        .insert(OrbitCamera::default())
+        .insert(PickingCameraBundle::default())
+        .insert(Name::new("Cube Camera"));
 }
@@ -1,29 +1,98 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, utils::HashMap, sprite::MaterialMesh2dBundle};
-use bevy_mod_picking::{PickableBundle, PickingCameraBundle};
+use bevy::{prelude::*, utils::HashMap, sprite::{Anchor, MaterialMesh2dBundle}};
+use bevy_mod_picking::{PickableBundle, PickingCameraBundle, PickingEvent};
 
-use crate::data::*;
+use crate::{data::*, logic::{can_swap_sides, load_tutorial_step, reset_game, side_profile, toggle_tutorial}};
 
 mod init;
 use init::*;
 
+mod atlas;
+use atlas::*;
+
+mod theme;
+use theme::*;
+
+mod tween;
+use tween::*;
+
+mod cube;
+use cube::*;
+
+mod infinite;
+use infinite::*;
+
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
         app
+            .add_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .add_asset::<AtlasManifest>()
+            .init_asset_loader::<AtlasManifestLoader>()
+            .add_event::<NextThemeEvent>()
+            .add_startup_system(load_themes.in_base_set(StartupSet::PreStartup))
             .add_startup_system(init_materials.in_base_set(StartupSet::PreStartup))
             .add_startup_system(init_textures.in_base_set(StartupSet::PreStartup))
+            .add_startup_system(init_fonts.in_base_set(StartupSet::PreStartup))
+            .add_startup_system(init_cube_materials.in_base_set(StartupSet::PreStartup))
             .add_startup_system(spawn_camera)
             .add_startup_system(spawn_board)
             .add_startup_system(spawn_turn_text)
+            .add_startup_system(spawn_clock_text)
+            .add_startup_system(spawn_profile_ui)
+            .add_startup_system(spawn_tutorial_tooltip)
+            .add_startup_system(spawn_mark_choice_indicator)
+            .add_startup_system(spawn_swap_text)
+            .add_startup_system(spawn_hint_ui)
             .add_startup_system(spawn_game_over_popup)
             .add_system(update_turn_text.in_schedule(OnEnter(GameState::XTurn)))
             .add_system(update_turn_text.in_schedule(OnEnter(GameState::OTurn)))
+            .add_system(warn_vanishing_mark.in_schedule(OnEnter(GameState::XTurn)))
+            .add_system(warn_vanishing_mark.in_schedule(OnEnter(GameState::OTurn)))
             .add_system(show_game_over_popup.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(spawn_strike_through.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(update_strike_throughs)
+            .add_system(update_tweens)
+            .add_system(clear_board.after(reset_game).before(load_tutorial_step))
+            .add_system(spawn_board
+                .run_if(variant_changed)
+                .after(clear_board)
+                .after(toggle_tutorial)
+                .before(load_tutorial_step))
+            // Before the rebuild, whose despawns would otherwise be followed by inserts on the old board
+            .add_system(update_sub_board_highlights.before(spawn_board))
+            .add_system(claim_sub_boards)
+            .add_system(animate_btn_press)
             .add_system(update_blinking_timers)
-            .add_system(update_delay_timers);
+            .add_system(update_delay_timers)
+            .add_system(cycle_theme)
+            .add_system(apply_theme.after(cycle_theme))
+            .add_system(update_text_color.after(apply_theme))
+            .add_system(update_clock_text)
+            .add_system(update_profile_text)
+            .add_system(toggle_profile_stats)
+            .add_system(update_profile_stats)
+            .add_system(tint_marks)
+            .add_system(show_achievement_toasts)
+            .add_system(stack_achievement_toasts)
+            .add_system(update_tutorial_tooltip)
+            .add_system(update_mark_choice_indicator)
+            .add_system(update_swap_text)
+            .add_system(show_hint)
+            .add_system(clear_hint.in_schedule(OnEnter(GameState::XTurn)))
+            .add_system(clear_hint.in_schedule(OnEnter(GameState::OTurn)))
+            .add_system(clear_hint.in_schedule(OnEnter(GameState::GameOver)))
+            .add_system(update_score_text)
+            .add_system(update_eval_labels)
+            .add_system(sync_cube_camera)
+            .add_system(update_orbit_camera)
+            .add_system(update_cube_cells)
+            .init_resource::<BoardView>()
+            .add_system(extend_infinite_board)
+            .add_system(update_board_view.after(spawn_board));
     }
 }
 
@@ -54,17 +123,437 @@ fn update_turn_text(
     }
 }
 
+// Fades out the mark that disappears once the player to move places another one
+fn warn_vanishing_mark(
+    mut commands: Commands,
+    game_state: Res<State<GameState>>,
+    rules: Res<Rules>,
+    placement_qry: Query<(Entity, &Placement)>,
+    children_qry: Query<&Children>,
+    mark_qry: Query<Entity, With<MarkSprite>>,
+) {
+    if rules.variant != Variant::Disappearing { return; }
+
+    let Some(vanishing) = vanishing_mark(placement_qry.iter(), game_state.0.turn_mark()) else { return; };
+    let Ok(children) = children_qry.get(vanishing) else { return; };
+    for mark_ent in mark_qry.iter_many(children) {
+        commands.entity(mark_ent).insert(Tween::new(
+            TweenTarget::Alpha { from: 1., to: 0.35 },
+            Duration::from_millis(300),
+            Ease::QuadOut,
+        ));
+    }
+}
+
+fn update_mark_choice_indicator(
+    mut indicator_qry: Query<(&mut TextureAtlasSprite, &mut Visibility), With<MarkChoiceIndicator>>,
+    mark_choice: Res<MarkChoice>,
+    rules: Res<Rules>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+) {
+    if !mark_choice.is_changed() && !rules.is_changed() { return; }
+
+    for (mut sprite, mut vis) in indicator_qry.iter_mut() {
+        *vis = if rules.wild { Visibility::Inherited } else { Visibility::Hidden };
+        sprite.index = if mark_choice.0 == CellState::X {
+            tex_atlas_indices.x
+        } else {
+            tex_atlas_indices.o
+        };
+    }
+}
+
+fn update_swap_text(
+    mut swap_text_qry: Query<&mut Visibility, With<SwapText>>,
+    settings: Res<StartSettings>,
+    round: Res<Round>,
+    players: Res<Players>,
+    game_state: Res<State<GameState>>,
+    placement_qry: Query<&Placement>,
+) {
+    let human_to_move = players.is_human(game_state.0.turn_mark());
+    let visible = human_to_move
+        && can_swap_sides(&settings, &round, &game_state.0, placement_qry.iter().count());
+    let new_vis = if visible { Visibility::Inherited } else { Visibility::Hidden };
+    for mut vis in swap_text_qry.iter_mut() {
+        if *vis != new_vis {
+            *vis = new_vis;
+        }
+    }
+}
+
+fn show_hint(
+    mut commands: Commands,
+    mut hint_given_evt_rdr: EventReader<HintGivenEvent>,
+    mut hint_text_qry: Query<(&mut Text, &mut Visibility), With<HintText>>,
+    mat_handles: Res<MaterialHandles>,
+    cube_mats: Res<CubeMaterials>,
+    hinted_qry: Query<(Entity, &CellState, Option<&CubeCell>), With<HintedCell>>,
+    cube_cell_qry: Query<(), With<CubeCell>>,
+) {
+    for evt in hint_given_evt_rdr.iter() {
+        for (ent, state, cube_cell) in hinted_qry.iter().filter(|(ent, ..)| *ent != evt.entity) {
+            commands.entity(ent).remove::<HintedCell>();
+            if cube_cell.is_none() {
+                commands.entity(ent).insert(mat_handles.transparent.clone_weak());
+            } else if *state == CellState::None {
+                commands.entity(ent).insert(cube_mats.empty.clone_weak());
+            }
+        }
+        commands.entity(evt.entity).insert(HintedCell);
+        // Cube cells are 3D meshes with their own materials
+        if cube_cell_qry.contains(evt.entity) {
+            commands.entity(evt.entity).insert(cube_mats.hovered.clone_weak());
+        } else {
+            commands.entity(evt.entity).insert(mat_handles.hint.clone_weak());
+        }
+
+        let mark = if evt.state == CellState::X { "X" } else { "O" };
+        for (mut text, mut vis) in hint_text_qry.iter_mut() {
+            text.sections[0].value = format!("hint: {} here {}", mark, evt.reason.label());
+            *vis = Visibility::Inherited;
+        }
+    }
+}
+
+fn clear_hint(
+    mut commands: Commands,
+    mut hint_text_qry: Query<&mut Visibility, With<HintText>>,
+    mat_handles: Res<MaterialHandles>,
+    cube_mats: Res<CubeMaterials>,
+    hinted_qry: Query<(Entity, &CellState, Option<&CubeCell>), With<HintedCell>>,
+) {
+    for (ent, state, cube_cell) in hinted_qry.iter() {
+        commands.entity(ent).remove::<HintedCell>();
+        if cube_cell.is_none() {
+            commands.entity(ent).insert(mat_handles.transparent.clone_weak());
+        } else if *state == CellState::None {
+            commands.entity(ent).insert(cube_mats.empty.clone_weak());
+        }
+    }
+    for mut vis in hint_text_qry.iter_mut() {
+        *vis = Visibility::Hidden;
+    }
+}
+
+fn update_score_text(
+    mut score_text_qry: Query<&mut Text, With<ScoreText>>,
+    score: Res<MatchScore>,
+    seating: Res<Seating>,
+) {
+    if !score.is_changed() && !seating.is_changed() { return; }
+
+    // Each person's score sits next to the mark they play now
+    let [first, second] = seating.marks.map(|mark| if mark == CellState::X { "X" } else { "O" });
+    for mut text in score_text_qry.iter_mut() {
+        text.sections[0].value = format!(
+            "{} {} - {} {}   draws {}   hints {}/{}",
+            first, score.wins[0], score.wins[1], second, score.draws, score.hints[0], score.hints[1],
+        );
+    }
+}
+
+// Labels read W/D/L for a win, draw or loss followed by the moves left until the game ends
+fn update_eval_labels(
+    mut commands: Commands,
+    overlay: Res<EvalOverlay>,
+    rules: Res<Rules>,
+    board: Res<Board>,
+    font_handle: Res<FontHandle>,
+    cell_qry: Query<&GlobalTransform>,
+    label_qry: Query<Entity, With<EvalLabel>>,
+) {
+    if !overlay.is_changed() && !rules.is_changed() { return; }
+
+    for ent in label_qry.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    if !overlay.enabled { return; }
+
+    // Boards too big to solve get a note instead of labels
+    if !rules.variant.can_be_solved() {
+        commands.spawn(Text2dBundle {
+            text: Text::from_section("no evaluation for this board", TextStyle {
+                font: font_handle.0.clone_weak(),
+                font_size: 24.,
+                color: Color::rgb(0.75, 0.75, 0.75),
+            }),
+            transform: Transform::from_translation(Vec3::new(0., -285., -98.)),
+            ..default()
+        })
+            .insert(EvalLabel)
+            .insert(Name::new("Eval Label"));
+        return;
+    }
+
+    for (pos, evaluation) in overlay.evaluations.iter() {
+        let Some(transform) = board.0.get(pos).and_then(|ent| cell_qry.get(*ent).ok()) else { continue; };
+        let (letter, color) = match evaluation.outcome {
+            Outcome::Win => ("W", Color::rgb(0.3, 0.85, 0.4)),
+            Outcome::Draw => ("D", Color::rgb(0.75, 0.75, 0.75)),
+            Outcome::Loss => ("L", Color::rgb(0.9, 0.3, 0.3)),
+        };
+        commands.spawn(Text2dBundle {
+            text: Text::from_section(format!("{}{}", letter, evaluation.moves), TextStyle {
+                font: font_handle.0.clone_weak(),
+                font_size: 30.,
+                color,
+            }),
+            transform: Transform::from_translation(transform.translation().truncate().extend(-98.)),
+            ..default()
+        })
+            .insert(EvalLabel)
+            .insert(Name::new("Eval Label"));
+    }
+}
+
+fn update_clock_text(
+    mut clock_text_qry: Query<(&mut Text, &mut Visibility), With<ClockText>>,
+    clock: Res<TurnClock>,
+    settings: Res<ClockSettings>,
+    game_state: Res<State<GameState>>,
+) {
+    let timer = match game_state.0.turn_mark() {
+        CellState::X => &clock.x,
+        CellState::O => &clock.o,
+        CellState::None => return,
+    };
+    let secs = timer.remaining().as_secs_f32().ceil() as u32;
+
+    for (mut text, mut vis) in clock_text_qry.iter_mut() {
+        *vis = if settings.mode == ClockMode::Off {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        let value = match settings.mode {
+            ClockMode::Game(_) => format!("{}:{:02}", secs / 60, secs % 60),
+            _ => secs.to_string(),
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// Profiles playing each side with their ratings in the variant, above the turn text. Profiles the computer
+// hasn't been rated under yet are shown as they will be created.
+fn update_profile_text(
+    mut profile_text_qry: Query<(&ProfileText, &mut Text)>,
+    mut avatar_qry: Query<(&ProfileAvatar, &mut TextureAtlasSprite)>,
+    profiles: Res<Profiles>,
+    players: Res<Players>,
+    rules: Res<Rules>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+) {
+    let profile = |side: CellState| {
+        let name = side_profile(&players, &profiles, side);
+        match profiles.get(&name) {
+            Some(profile) => (name, profile.rating(rules.variant), profile.avatar, profile.mark_color()),
+            None => (name, INITIAL_RATING, Avatar::default(), Color::WHITE),
+        }
+    };
+
+    for (ProfileText(side), mut text) in profile_text_qry.iter_mut() {
+        let (name, rating, ..) = profile(*side);
+        let value = format!("{} {:.0}", name, rating);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+    for (ProfileAvatar(side), mut sprite) in avatar_qry.iter_mut() {
+        let (_, _, avatar, color) = profile(*side);
+        let index = match avatar {
+            Avatar::Cross => tex_atlas_indices.x,
+            Avatar::Ring => tex_atlas_indices.o,
+        };
+        if sprite.index != index || sprite.color != color {
+            sprite.index = index;
+            sprite.color = color;
+        }
+    }
+}
+
+fn toggle_profile_stats(
+    mut toggle_profile_stats_evt_rdr: EventReader<ToggleProfileStatsEvent>,
+    mut panel_qry: Query<&mut Visibility, With<ProfileStatsPanel>>,
+) {
+    for _ in toggle_profile_stats_evt_rdr.iter() {
+        for mut vis in panel_qry.iter_mut() {
+            *vis = if *vis == Visibility::Hidden { Visibility::Inherited } else { Visibility::Hidden };
+        }
+    }
+}
+
+fn update_profile_stats(
+    mut stats_text_qry: Query<&mut Text, With<ProfileStatsText>>,
+    profiles: Res<Profiles>,
+    players: Res<Players>,
+    rules: Res<Rules>,
+) {
+    if !profiles.is_changed() && !players.is_changed() && !rules.is_changed() { return; }
+
+    let mut value = String::new();
+    for side in [CellState::X, CellState::O] {
+        let name = side_profile(&players, &profiles, side);
+        let profile = profiles.get(&name).cloned().unwrap_or_else(|| Profile::new(&name));
+        let stats = profile.stats();
+        let opening = stats.favorite_opening
+            .map_or("none yet".to_string(), |pos| format!("{},{},{}", pos.row, pos.col, pos.layer));
+        value.push_str(&format!(
+            "{:?}: {} ({:.0})\n{} games, {:.0}% won\nlongest win streak {}\n{:.1} moves a game\nfavorite opening {}\nachievements {}/{}\n\n",
+            side, profile.name, profile.rating(rules.variant),
+            stats.games, stats.win_rate * 100.,
+            stats.longest_streak,
+            stats.average_length,
+            opening,
+            profile.achievements.len(), Achievement::ALL.len(),
+        ));
+    }
+    value.push_str("1/2 profile  3/4 color  5/6 avatar");
+
+    for mut text in stats_text_qry.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// Top of the toast stack, just below the profile lines
+const TOAST_TOP: f32 = 290.;
+
+const TOAST_LINE_HEIGHT: f32 = 28.;
+
+fn toast_height(text: &Text) -> f32 {
+    text.sections[0].value.lines().count() as f32 * TOAST_LINE_HEIGHT
+}
+
+// Toasts stack downwards below the profile lines and fade out after a few seconds
+fn show_achievement_toasts(
+    mut commands: Commands,
+    mut achievement_evt_rdr: EventReader<AchievementUnlockedEvent>,
+    mut shown: Local<u32>,
+    toast_qry: Query<&Text, With<AchievementToast>>,
+    font_handle: Res<FontHandle>,
+) {
+    let mut y = TOAST_TOP - toast_qry.iter().map(toast_height).sum::<f32>();
+    for evt in achievement_evt_rdr.iter() {
+        let mut message = format!("{} unlocked: {}", evt.profile, evt.achievement.title());
+        if evt.achievement.theme_reward().is_some() {
+            message.push_str("\nnew theme, press T");
+        }
+        let text = Text::from_section(message, TextStyle {
+            font: font_handle.0.clone_weak(),
+            font_size: 24.,
+            color: Color::WHITE,
+        });
+        let height = toast_height(&text);
+        commands.spawn(Text2dBundle {
+            text,
+            text_anchor: Anchor::TopCenter,
+            transform: Transform::from_translation(Vec3::new(0., y, -96.)),
+            ..default()
+        })
+            .insert(AchievementToast(*shown))
+            .insert(Tween::new(
+                TweenTarget::Alpha { from: 1., to: 0. },
+                Duration::from_millis(600),
+                Ease::QuadIn,
+            ).with_delay(Duration::from_secs(3)).with_despawn())
+            .insert(Name::new("Achievement Toast"));
+        y -= height;
+        *shown += 1;
+    }
+}
+
+// The toasts still shown move up into the space of the ones that faded out
+fn stack_achievement_toasts(
+    mut toast_qry: Query<(&AchievementToast, &Text, &mut Transform)>,
+) {
+    let mut toasts: Vec<_> = toast_qry.iter_mut().collect();
+    toasts.sort_by_key(|(toast, ..)| toast.0);
+    let mut y = TOAST_TOP;
+    for (_, text, mut transform) in toasts {
+        if transform.translation.y != y {
+            transform.translation.y = y;
+        }
+        y -= toast_height(text);
+    }
+}
+
+fn update_tutorial_tooltip(
+    mut tooltip_qry: Query<&mut Visibility, With<TutorialTooltip>>,
+    mut tutorial_text_qry: Query<&mut Text, With<TutorialText>>,
+    tutorial: Res<Tutorial>,
+) {
+    if !tutorial.is_changed() { return; }
+
+    let new_vis = if tutorial.step.is_some() { Visibility::Inherited } else { Visibility::Hidden };
+    for mut vis in tooltip_qry.iter_mut() {
+        if *vis != new_vis {
+            *vis = new_vis;
+        }
+    }
+
+    let Some(step) = tutorial.step else { return; };
+    let lesson = &TUTORIAL[step];
+    let mut value = format!("{}/{} {}\n{}", step + 1, TUTORIAL.len(), lesson.title, lesson.text);
+    match &tutorial.feedback {
+        Some((true, _)) => value = format!("{}\n\n{}", lesson.title, lesson.success),
+        Some((false, _)) => value = format!("{}\n\n{}", lesson.title, lesson.mistake),
+        None if tutorial.blocked_click => value.push_str("\nThis lesson is about other cells."),
+        None => (),
+    }
+    for mut text in tutorial_text_qry.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// Marks are drawn in the color of the profile that placed them, keeping the alpha of any tween
+fn tint_marks(
+    mut mark_qry: Query<&mut TextureAtlasSprite, With<MarkSprite>>,
+    cell_qry: Query<(&Placement, &Children)>,
+    profiles: Res<Profiles>,
+    players: Res<Players>,
+) {
+    let colors = [CellState::X, CellState::O].map(|side| {
+        let name = side_profile(&players, &profiles, side);
+        profiles.get(&name).map_or(Color::WHITE, |profile| profile.mark_color())
+    });
+    for (placement, children) in cell_qry.iter() {
+        let color = if placement.player == CellState::O { colors[1] } else { colors[0] };
+        let mut marks = mark_qry.iter_many_mut(children);
+        while let Some(mut sprite) = marks.fetch_next() {
+            let tinted = color.with_a(sprite.color.a());
+            if sprite.color != tinted {
+                sprite.color = tinted;
+            }
+        }
+    }
+}
+
 fn show_game_over_popup(
     mut commands: Commands,
     mut game_over_evt_rdr: EventReader<GameOverEvent>,
     mut popup_qry: Query<Entity, With<GameOverPopup>>,
-    mut name_qry: Query<(&Name, &mut Visibility)>,
+    mut name_qry: Query<(&Name, &mut Visibility), Without<DrawText>>,
+    mut draw_text_qry: Query<&mut Visibility, With<DrawText>>,
+    params: Res<Params>,
 ) {
     for evt in game_over_evt_rdr.iter() {
-        let cell_ent = evt.last_picked_cell_ent;
-        let state = evt.last_picked_cell_state;
+        let state = evt.winner;
         let popup_ent = popup_qry.single_mut();
 
+        for mut vis in draw_text_qry.iter_mut() {
+            *vis = if state == CellState::None {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+
         for (name, mut vis) in name_qry.iter_mut() {
             let name = name.to_string();
             // marker components may be better than querying entire scene for specific names
@@ -77,9 +566,208 @@ fn show_game_over_popup(
             }
         }
         
-        commands.entity(popup_ent).insert(DelayTimer(
-            Timer::new(Duration::from_millis(1000), TimerMode::Once)
-        ));
+        commands.entity(popup_ent)
+            .insert(DelayTimer(
+                Timer::new(Duration::from_millis(POPUP_DELAY_MS), TimerMode::Once)
+            ))
+            .insert(Tween::new(
+                TweenTarget::Translation {
+                    from: Vec3::new(0., params.window_height, -99.),
+                    to: Vec3::new(0., 0., -99.),
+                },
+                Duration::from_millis(300),
+                Ease::CubicOut,
+            ).with_delay(Duration::from_millis(POPUP_DELAY_MS)));
+    }
+}
+
+// Only the variant decides the board's layout, misère and wild games keep their board.
+// The startup board is spawned by spawn_board itself.
+fn variant_changed(rules: Res<Rules>, mut prev_variant: Local<Option<Variant>>) -> bool {
+    let changed = prev_variant.map_or(false, |prev| prev != rules.variant);
+    *prev_variant = Some(rules.variant);
+    changed
+}
+
+fn update_sub_board_highlights(
+    mut commands: Commands,
+    sub_board_qry: Query<(Entity, &SubBoard), Without<CellState>>,
+    added_qry: Query<(), (Added<SubBoard>, Without<CellState>)>,
+    ultimate: Res<UltimateBoard>,
+    game_state: Res<State<GameState>>,
+    mat_handles: Res<MaterialHandles>,
+) {
+    // A rebuilt board is highlighted the frame after it was spawned
+    if !ultimate.is_changed() && !game_state.is_changed() && added_qry.is_empty() { return; }
+    if game_state.0 == GameState::GameOver { return; }
+
+    for (ent, sub_board) in sub_board_qry.iter() {
+        let material = if ultimate.is_playable(sub_board.0) {
+            &mat_handles.hovered
+        } else {
+            &mat_handles.transparent
+        };
+        commands.entity(ent).insert(material.clone_weak());
+    }
+}
+
+fn claim_sub_boards(
+    mut commands: Commands,
+    mut sub_board_won_evt_rdr: EventReader<SubBoardWonEvent>,
+    tex_atlas_handle: Res<TextureAtlasHandle>,
+    tex_atlas_indices: Res<TextureAtlasIndices>,
+    board: Res<Board>,
+    params: Res<Params>,
+) {
+    for evt in sub_board_won_evt_rdr.iter() {
+        let Some(sub_board_ent) = board.0.get(&evt.sub_board) else { continue; };
+        let sprite_index = if evt.winner == CellState::X {
+            tex_atlas_indices.x
+        } else {
+            tex_atlas_indices.o
+        };
+        let scale = Vec3::splat(params.tile_size * 1.12 * 0.05);
+        let mark_ent = commands.spawn(SpriteSheetBundle {
+            texture_atlas: tex_atlas_handle.0.clone_weak(),
+            sprite: TextureAtlasSprite::new(sprite_index),
+            transform: Transform::from_translation(Vec3::Z).with_scale(Vec3::ZERO),
+            ..default()
+        })
+            .insert(MarkSprite)
+            .insert(Tween::new(
+                TweenTarget::Scale { from: Vec3::ZERO, to: scale },
+                Duration::from_millis(300),
+                Ease::BackOut,
+            ))
+            .id();
+        commands.entity(*sub_board_ent).add_child(mark_ent);
+    }
+}
+
+fn clear_board(
+    mut commands: Commands,
+    mut board_cleared_evt_rdr: EventReader<BoardClearedEvent>,
+    mut cell_qry: Query<(Entity, &mut Visibility, Option<&CubeCell>), Or<(With<CellState>, With<SubBoard>)>>,
+    mark_qry: Query<Entity, With<MarkSprite>>,
+    strike_qry: Query<(Entity, &Transform), With<StrikeThrough>>,
+    popup_qry: Query<(Entity, &Transform), With<GameOverPopup>>,
+    mat_handles: Res<MaterialHandles>,
+    params: Res<Params>,
+) {
+    if board_cleared_evt_rdr.iter().count() == 0 { return; }
+
+    for ent in mark_qry.iter() {
+        commands.entity(ent).insert(Tween::new(
+            TweenTarget::Alpha { from: 1., to: 0. },
+            Duration::from_millis(250),
+            Ease::QuadIn,
+        ).with_despawn());
+    }
+    for (ent, mut vis, cube_cell) in cell_qry.iter_mut() {
+        *vis = Visibility::Inherited;
+        commands.entity(ent).remove::<BlinkingTimer>();
+        // Cube cells get their materials from update_cube_cells
+        if cube_cell.is_none() {
+            commands.entity(ent).insert(mat_handles.transparent.clone_weak());
+        }
+    }
+    for (ent, transform) in strike_qry.iter() {
+        commands.entity(ent)
+            .remove::<StrikeThrough>()
+            .insert(Tween::new(
+                TweenTarget::Scale { from: transform.scale, to: Vec3::ZERO },
+                Duration::from_millis(250),
+                Ease::QuadOut,
+            ).with_despawn());
+    }
+    for (ent, transform) in popup_qry.iter() {
+        commands.entity(ent)
+            .remove::<DelayTimer>()
+            .insert(Tween::new(
+                TweenTarget::Translation {
+                    from: transform.translation,
+                    to: Vec3::new(0., params.window_height, -99.),
+                },
+                Duration::from_millis(250),
+                Ease::QuadIn,
+            ));
+    }
+}
+
+fn animate_btn_press(
+    mut commands: Commands,
+    mut picking_evt_rdr: EventReader<PickingEvent>,
+    btn_qry: Query<Entity, Or<(With<PlayBtn>, With<QuitBtn>)>>,
+) {
+    for evt in picking_evt_rdr.iter() {
+        if let PickingEvent::Clicked(ent) = evt {
+            if btn_qry.contains(*ent) {
+                commands.entity(*ent).insert(Tween::new(
+                    TweenTarget::Scale { from: Vec3::ONE, to: Vec3::splat(0.85) },
+                    Duration::from_millis(150),
+                    Ease::Linear,
+                ).with_yoyo());
+            }
+        }
+    }
+}
+
+fn spawn_strike_through(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut game_over_evt_rdr: EventReader<GameOverEvent>,
+    cell_qry: Query<&GlobalTransform>,
+    board: Res<Board>,
+    rules: Res<Rules>,
+    mat_handles: Res<MaterialHandles>,
+    params: Res<Params>,
+) {
+    for evt in game_over_evt_rdr.iter() {
+        let Some(winning_positions) = &evt.winning_positions else { continue; };
+        // Cube boards highlight their winning cells instead, see update_cube_cells
+        if rules.variant.is_cube() { continue; }
+        let cell_translation = |pos: &CellPosition| {
+            board.0.get(pos)
+                .and_then(|ent| cell_qry.get(*ent).ok())
+                .map(|transform| transform.translation())
+        };
+        let (Some(first), Some(last)) = (
+            cell_translation(&winning_positions[0]),
+            cell_translation(&winning_positions[winning_positions.len() - 1]),
+        ) else { continue; };
+
+        // Overshoot the outer cells a little so the line reads as crossing them out
+        let dir = (last - first).normalize_or_zero();
+        let overshoot = dir * params.tile_size * 0.4;
+        let z = Vec3::Z * -99.5;
+        commands.spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            material: mat_handles.strike.clone_weak(),
+            transform: Transform::from_scale(Vec3::ZERO),
+            ..default()
+        })
+            .insert(StrikeThrough::new(
+                (first - overshoot) * Vec3::new(1., 1., 0.) + z,
+                (last + overshoot) * Vec3::new(1., 1., 0.) + z,
+                params.tile_size * 0.1,
+            ))
+            .insert(Name::new("Strike Through"));
+    }
+}
+
+fn update_strike_throughs(
+    mut strike_qry: Query<(&mut StrikeThrough, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (mut strike, mut transform) in strike_qry.iter_mut() {
+        if strike.finished() { continue; }
+        strike.tick(time.delta());
+
+        let full = strike.to - strike.from;
+        let grown = full * strike.progress();
+        *transform = Transform::from_translation(strike.from + grown / 2.)
+            .with_rotation(Quat::from_rotation_z(full.y.atan2(full.x)))
+            .with_scale(Vec3::new(grown.length(), strike.thickness, 1.));
     }
 }
 
@@ -0,0 +1,181 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset, AssetPath},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use crate::data::*;
+use super::atlas::build_texture_atlas;
+
+const THEME_PATHS: [&str; 4] = [
+    "themes/default.theme.ron",
+    "themes/high_contrast.theme.ron",
+    "themes/sunset.theme.ron",
+    "themes/midnight.theme.ron",
+];
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let file: ThemeFile = ron::de::from_bytes(bytes)?;
+            let palette = Palette {
+                hovered: parse_color(&file.palette.hovered)?,
+                winner: parse_color(&file.palette.winner)?,
+                strike: parse_color(&file.palette.strike)?,
+                bg: parse_color(&file.palette.bg)?,
+                hint: parse_color(&file.palette.hint)?,
+                text: parse_color(&file.palette.text)?,
+            };
+            let atlas_path = AssetPath::new(file.atlas.into(), None);
+            let atlas = load_context.get_handle(atlas_path.get_id());
+
+            load_context.set_default_asset(
+                LoadedAsset::new(Theme {
+                    name: file.name,
+                    palette,
+                    atlas,
+                })
+                .with_dependency(atlas_path)
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+fn parse_color(hex: &str) -> Result<Color, bevy::asset::Error> {
+    Color::hex(hex.trim_start_matches('#'))
+        .map_err(|err| bevy::asset::Error::msg(format!("invalid color {:?}: {}", hex, err)))
+}
+
+pub fn load_themes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(Themes {
+        handles: THEME_PATHS.iter().map(|path| asset_server.load(*path)).collect(),
+        locked_by: THEME_PATHS.iter()
+            .map(|path| Achievement::ALL.into_iter().find(|achievement| achievement.theme_reward() == Some(*path)))
+            .collect(),
+        current: 0,
+    });
+}
+
+pub fn cycle_theme(
+    mut next_theme_evt_rdr: EventReader<NextThemeEvent>,
+    mut themes: ResMut<Themes>,
+    profiles: Res<Profiles>,
+) {
+    for _ in next_theme_evt_rdr.iter() {
+        // Locked themes are skipped, the current one is always available to come back to
+        let len = themes.handles.len();
+        let next = (1..=len)
+            .map(|offset| (themes.current + offset) % len)
+            .find(|index| themes.locked_by[*index].map_or(true, |achievement| profiles.has_unlocked(achievement)));
+        if let Some(next) = next {
+            themes.current = next;
+        }
+    }
+}
+
+// Reapplies the current theme whenever it is switched or any file it is built from
+// is (re)loaded, which also covers hot reloading the manifest and atlas image
+pub fn apply_theme(
+    mut theme_evt_rdr: EventReader<AssetEvent<Theme>>,
+    mut manifest_evt_rdr: EventReader<AssetEvent<AtlasManifest>>,
+    mut image_evt_rdr: EventReader<AssetEvent<Image>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut cube_materials: ResMut<Assets<StandardMaterial>>,
+    mut tex_atlases: ResMut<Assets<TextureAtlas>>,
+    mut text_color: ResMut<TextColor>,
+    mut pending: Local<bool>,
+    themes: Res<Themes>,
+    theme_assets: Res<Assets<Theme>>,
+    manifests: Res<Assets<AtlasManifest>>,
+    images: Res<Assets<Image>>,
+    mat_handles: Res<MaterialHandles>,
+    cube_mats: Res<CubeMaterials>,
+    tex_atlas_handle: Res<TextureAtlasHandle>,
+) {
+    let theme = theme_assets.get(themes.current_handle());
+    let manifest = theme.and_then(|theme| manifests.get(&theme.atlas));
+    let image = manifest.and_then(|manifest| images.get(&manifest.image));
+
+    *pending |= themes.is_changed();
+    for evt in theme_evt_rdr.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = evt {
+            *pending |= handle == themes.current_handle();
+        }
+    }
+    for evt in manifest_evt_rdr.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = evt {
+            *pending |= theme.map_or(false, |theme| *handle == theme.atlas);
+        }
+    }
+    for evt in image_evt_rdr.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = evt {
+            *pending |= manifest.map_or(false, |manifest| *handle == manifest.image);
+        }
+    }
+    if !*pending { return; }
+
+    // Dependencies may still be loading, try again next frame
+    let (Some(theme), Some(manifest), Some(image)) = (theme, manifest, image) else { return; };
+    *pending = false;
+
+    match build_texture_atlas(manifest, image) {
+        Ok(tex_atlas) => {
+            tex_atlases.set_untracked(&tex_atlas_handle.0, tex_atlas);
+        },
+        Err(err) => {
+            error!("theme {} has an invalid atlas: {}", theme.name, err);
+            return;
+        },
+    }
+    info!("applying theme {}", theme.name);
+
+    let palette = &theme.palette;
+    for (handle, color) in [
+        (&mat_handles.hovered, palette.hovered),
+        (&mat_handles.winner, palette.winner),
+        (&mat_handles.strike, palette.strike),
+        (&mat_handles.bg, palette.bg),
+        (&mat_handles.hint, palette.hint),
+    ] {
+        if let Some(mat) = materials.get_mut(handle) {
+            mat.color = color;
+        }
+    }
+    for (handle, color) in [
+        (&cube_mats.hovered, palette.hovered),
+        (&cube_mats.winner, palette.winner),
+    ] {
+        if let Some(mat) = cube_materials.get_mut(handle) {
+            mat.base_color = color;
+        }
+    }
+    text_color.0 = palette.text;
+}
+
+pub fn update_text_color(
+    // Evaluation labels are colored by outcome instead
+    mut text_qry: Query<&mut Text, Without<EvalLabel>>,
+    text_color: Res<TextColor>,
+) {
+    for mut text in text_qry.iter_mut() {
+        if !text_color.is_changed() && !text.is_added() { continue; }
+        for section in text.sections.iter_mut() {
+            section.style.color = text_color.0;
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+use crate::data::*;
+
+pub fn update_tweens(
+    mut commands: Commands,
+    mut tween_qry: Query<(
+        Entity,
+        &mut Tween,
+        &mut Transform,
+        Option<&mut TextureAtlasSprite>,
+        Option<&mut Sprite>,
+        Option<&mut Text>,
+    )>,
+    time: Res<Time>,
+) {
+    for (ent, mut tween, mut transform, atlas_sprite, sprite, text) in tween_qry.iter_mut() {
+        tween.tick(time.delta());
+        let t = tween.progress();
+
+        match tween.target {
+            TweenTarget::Scale { from, to } => {
+                transform.scale = from.lerp(to, t);
+            },
+            TweenTarget::Translation { from, to } => {
+                transform.translation = from.lerp(to, t);
+            },
+            TweenTarget::Alpha { from, to } => {
+                let alpha = from + (to - from) * t;
+                if let Some(mut sprite) = atlas_sprite {
+                    sprite.color.set_a(alpha);
+                }
+                if let Some(mut sprite) = sprite {
+                    sprite.color.set_a(alpha);
+                }
+                if let Some(mut text) = text {
+                    for section in text.sections.iter_mut() {
+                        section.style.color.set_a(alpha);
+                    }
+                }
+            },
+        }
+
+        if tween.finished() {
+            if tween.despawns() {
+                commands.entity(ent).despawn_recursive();
+            } else {
+                commands.entity(ent).remove::<Tween>();
+            }
+        }
+    }
+}